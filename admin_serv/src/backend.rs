@@ -1,22 +1,36 @@
+pub mod api;
+pub mod auth;
+pub mod callback;
+pub mod channel;
 pub mod config;
+pub mod contact;
 pub mod cron;
+pub mod dashboard;
+pub mod export;
+pub mod history;
+pub mod org;
+mod pipeline;
 mod push_notice;
+pub mod scheduler;
 mod session;
 mod xxscore;
-pub mod api;
 
 use crate::backend::config::AdminConfig;
+use crate::backend::contact::ContactMap;
+use crate::backend::cron::start_daily_notice;
+use crate::backend::org::Orgs;
 use anyhow::Result;
-use cron::start_daily_notice;
-pub use session::StateSession;
+pub use session::{Services, StateSession};
 use std::fs;
 use tokio::signal;
 use tracing::info;
 use wx::MP;
+pub use xxscore::fetcher::AdminLogin;
+pub use xxscore::Leaderboard;
 
-pub async fn serve(config: &str) -> Result<()> {
+pub async fn serve(config: &str, orgs: Orgs, contacts: ContactMap) -> Result<()> {
     tokio::select! {
-        r = start_daily_notice(config) => {
+        r = start_daily_notice(config, orgs, contacts) => {
             r?
         },
         _ = signal::ctrl_c() => {
@@ -1,7 +1,14 @@
-use crate::backend::StateSession;
-use crate::state::State;
+use crate::backend::auth::require_login;
+use crate::backend::dashboard;
+use crate::backend::export::{export, file_name, ExportFormat};
+use crate::backend::org::Orgs;
+use crate::state::{Dashboard, State};
 use anyhow::{anyhow, Result};
+use axum::extract::Query;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
 use axum::Extension;
+use chrono::NaiveDate;
 use dioxus_fullstack::prelude::extract;
 use serde::{Deserialize, Serialize};
 use std::thread;
@@ -10,11 +17,141 @@ use tokio::time;
 use tokio::time::sleep;
 use tracing::{error, info, instrument, warn};
 
-#[instrument(skip_all, level = "info")]
-pub async fn try_get_state() -> Result<State> {
-    let Extension(ss): Extension<StateSession> = extract().await?;
+#[instrument(skip_all, level = "info", fields(org = org))]
+pub async fn try_get_state(org: &str) -> Result<State> {
+    require_login().await?;
+    let Extension(orgs): Extension<Orgs> = extract().await?;
+    let org = orgs.get(org).ok_or(anyhow!("没有这个组织: {}", org))?;
 
-    let state = ss.get().await?;
+    let state = org.ss.get().await?;
 
     Ok(state)
 }
+
+pub async fn org_names() -> Result<Vec<String>> {
+    require_login().await?;
+    let Extension(orgs): Extension<Orgs> = extract().await?;
+    Ok(orgs.names())
+}
+
+#[instrument(level = "info")]
+pub async fn dashboard(org: &str, days: i64) -> Result<Dashboard> {
+    require_login().await?;
+    let Extension(orgs): Extension<Orgs> = extract().await?;
+    let org = orgs.get(org).ok_or(anyhow!("没有这个组织: {}", org))?;
+    dashboard::load(&org.history, org.org_id, days)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportQuery {
+    org: Option<String>,
+    start: Option<String>, // %Y%m%d，不传时导出最近一天
+    end: Option<String>,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// GET /export?org=default&start=20231101&end=20231130&format=xlsx
+#[instrument(skip(orgs))]
+pub async fn export_scores(
+    Extension(orgs): Extension<Orgs>,
+    Query(q): Query<ExportQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let org = match &q.org {
+        Some(name) => orgs
+            .get(name)
+            .ok_or((StatusCode::NOT_FOUND, format!("没有这个组织: {}", name)))?,
+        None => orgs.default_org(),
+    };
+    let scores = match &q.start {
+        Some(start) => {
+            let (start, end) = parse_range(start, q.end.as_deref())?;
+            org.history
+                .range(&start, &end)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        }
+        None => org
+            .history
+            .latest()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .into_iter()
+            .collect(),
+    };
+    if scores.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "没有积分数据".to_string()));
+    }
+    let data = export(&scores, q.format).map_err(|e| {
+        warn!("导出积分失败: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, q.format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name(&scores, q.format)),
+            ),
+        ],
+        data,
+    ))
+}
+
+/// 检查导出的日期范围，end 不传时只导出 start 这一天
+fn parse_range(start: &str, end: Option<&str>) -> Result<(String, String), (StatusCode, String)> {
+    let parse = |s: &str| {
+        NaiveDate::parse_from_str(s, "%Y%m%d").map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("日期格式有误，应为 20231101: {}", s),
+            )
+        })
+    };
+    let start = parse(start)?;
+    let end = match end {
+        Some(end) => parse(end)?,
+        None => start,
+    };
+    if start > end {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "开始日期不能晚于结束日期".to_string(),
+        ));
+    }
+    Ok((
+        start.format("%Y%m%d").to_string(),
+        end.format("%Y%m%d").to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        let ok = |s: &str, e: &str| Ok((s.to_string(), e.to_string()));
+        assert_eq!(
+            parse_range("20231101", Some("20231130")),
+            ok("20231101", "20231130")
+        );
+        assert_eq!(parse_range("20231101", None), ok("20231101", "20231101"));
+        assert_eq!(
+            parse_range("20231101", Some("20231101")),
+            ok("20231101", "20231101")
+        );
+        for (start, end) in [
+            ("20231201", Some("20231101")),
+            ("2023", None),
+            ("20231101", Some("2023-11-30")),
+            ("20231332", None),
+        ] {
+            assert_eq!(
+                parse_range(start, end).unwrap_err().0,
+                StatusCode::BAD_REQUEST,
+                "{} {:?}",
+                start,
+                end
+            );
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! 管理后台网页登录：企业微信网页授权(snsapi_base)拿到 UserID，
+//! 在允许的管理员名单里就发一个 hmac 签名的 cookie
+
+use crate::backend::config::AuthConfig;
+use anyhow::{anyhow, Result};
+use axum::extract::Query;
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::get;
+use axum::{Extension, Router};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use dioxus_fullstack::prelude::extract;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{info, instrument, warn};
+use wx::{OAuthApi, MP};
+
+const SESSION_COOKIE: &str = "xx_admin_session";
+/// 授权跳转的 state 有效期(秒)
+const STATE_TTL: i64 = 600;
+
+#[derive(Clone)]
+pub struct Auth(Option<Arc<(AuthConfig, MP)>>);
+
+impl Auth {
+    /// conf 为 None 时不校验登录
+    pub fn new(conf: Option<AuthConfig>, mp: MP) -> Self {
+        if conf.is_none() {
+            warn!("没有配置 [auth]，任何人都可以访问管理后台");
+        }
+        Self(conf.map(|c| Arc::new((c, mp))))
+    }
+
+    /// 没有开启登录时也返回 true
+    pub fn allows(&self, headers: &HeaderMap) -> bool {
+        match &self.0 {
+            Some(a) => session_user(&a.0, headers, Utc::now().timestamp()).is_some(),
+            None => true,
+        }
+    }
+}
+
+fn mac(secret: &str) -> Hmac<sha2::Sha256> {
+    Hmac::new_from_slice(secret.as_bytes()).expect("hmac 可以使用任意长度的密钥")
+}
+
+fn sign(secret: &str, payload: &str) -> String {
+    let mut m = mac(secret);
+    m.update(payload.as_bytes());
+    URL_SAFE_NO_PAD.encode(m.finalize().into_bytes())
+}
+
+fn verify(secret: &str, payload: &str, sig: &str) -> bool {
+    let Ok(sig) = URL_SAFE_NO_PAD.decode(sig) else {
+        return false;
+    };
+    let mut m = mac(secret);
+    m.update(payload.as_bytes());
+    m.verify_slice(&sig).is_ok()
+}
+
+/// base64(UserID).过期时间.签名
+fn session_token(secret: &str, user: &str, expires: i64) -> String {
+    let payload = format!("{}.{}", URL_SAFE_NO_PAD.encode(user), expires);
+    let sig = sign(secret, &payload);
+    format!("{}.{}", payload, sig)
+}
+
+/// 签名正确、没有过期并且还在管理员名单里才返回 UserID
+fn verify_session(conf: &AuthConfig, token: &str, now: i64) -> Option<String> {
+    let (payload, sig) = token.rsplit_once('.')?;
+    if !verify(&conf.session_secret, payload, sig) {
+        return None;
+    }
+    let (user, expires) = payload.split_once('.')?;
+    if expires.parse::<i64>().ok()? < now {
+        return None;
+    }
+    let user = String::from_utf8(URL_SAFE_NO_PAD.decode(user).ok()?).ok()?;
+    conf.admin_users.contains(&user).then_some(user)
+}
+
+/// 授权跳转带上签名的时间戳，回调时校验，防止伪造登录请求
+fn oauth_state(secret: &str, now: i64) -> String {
+    format!("{}.{}", now, sign(secret, &format!("state.{}", now)))
+}
+
+fn verify_state(secret: &str, state: &str, now: i64) -> bool {
+    let Some((ts, sig)) = state.split_once('.') else {
+        return false;
+    };
+    let Ok(t) = ts.parse::<i64>() else {
+        return false;
+    };
+    (0..=STATE_TTL).contains(&(now - t)) && verify(secret, &format!("state.{}", ts), sig)
+}
+
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|kv| kv.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v)
+}
+
+fn session_user(conf: &AuthConfig, headers: &HeaderMap, now: i64) -> Option<String> {
+    verify_session(conf, cookie(headers, SESSION_COOKIE)?, now)
+}
+
+fn session_cookie(conf: &AuthConfig, value: &str, max_age: i64) -> String {
+    let secure = if conf.base_url.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+    format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}",
+        SESSION_COOKIE, value, max_age, secure
+    )
+}
+
+/// 服务端函数开头调用，没有登录时返回错误
+pub async fn require_login() -> Result<()> {
+    let Extension(auth): Extension<Auth> = extract().await?;
+    let headers: HeaderMap = extract().await?;
+    if auth.allows(&headers) {
+        Ok(())
+    } else {
+        Err(anyhow!("请先登录"))
+    }
+}
+
+/// 没有登录时网页跳转到企业微信授权，其他请求返回 401
+pub async fn login_layer<B>(
+    Extension(auth): Extension<Auth>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if auth.allows(req.headers()) {
+        return next.run(req).await;
+    }
+    let html = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"));
+    if req.method() == Method::GET && html {
+        Redirect::temporary("/auth/login").into_response()
+    } else {
+        (StatusCode::UNAUTHORIZED, "请先登录").into_response()
+    }
+}
+
+async fn login(Extension(auth): Extension<Auth>) -> Response {
+    let Some(a) = &auth.0 else {
+        return Redirect::temporary("/").into_response();
+    };
+    let (conf, mp) = a.as_ref();
+    let redirect_uri = format!("{}/auth/callback", conf.base_url.trim_end_matches('/'));
+    let state = oauth_state(&conf.session_secret, Utc::now().timestamp());
+    Redirect::temporary(&mp.oauth_url(&redirect_uri, &state)).into_response()
+}
+
+#[derive(Deserialize, Debug)]
+struct CallbackQuery {
+    code: String,
+    state: String,
+}
+
+#[instrument(skip_all)]
+async fn callback(
+    Extension(auth): Extension<Auth>,
+    Query(q): Query<CallbackQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(a) = &auth.0 else {
+        return Ok(Redirect::temporary("/").into_response());
+    };
+    let (conf, mp) = a.as_ref();
+    let now = Utc::now().timestamp();
+    if !verify_state(&conf.session_secret, &q.state, now) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "登录链接已失效，请重新打开管理后台".to_string(),
+        ));
+    }
+    let user = mp.user_id_by_code(&q.code).await.map_err(|e| {
+        warn!("企业微信网页授权失败: {}", e);
+        (StatusCode::UNAUTHORIZED, "企业微信授权失败".to_string())
+    })?;
+    if !conf.admin_users.contains(&user) {
+        warn!(user, "不在管理员名单里的成员尝试登录");
+        return Err((StatusCode::FORBIDDEN, format!("{} 没有管理后台权限", user)));
+    }
+    info!(user, "登录管理后台");
+    let max_age = conf.session_hours * 3600;
+    let token = session_token(&conf.session_secret, &user, now + max_age);
+    Ok((
+        [(header::SET_COOKIE, session_cookie(conf, &token, max_age))],
+        Redirect::temporary("/"),
+    )
+        .into_response())
+}
+
+async fn logout(Extension(auth): Extension<Auth>) -> Response {
+    match &auth.0 {
+        Some(a) => (
+            [(header::SET_COOKIE, session_cookie(&a.0, "", 0))],
+            "已退出登录",
+        )
+            .into_response(),
+        None => Redirect::temporary("/").into_response(),
+    }
+}
+
+/// /auth/login、/auth/callback、/auth/logout，不需要登录
+pub fn router() -> Router {
+    Router::new()
+        .route("/auth/login", get(login))
+        .route("/auth/callback", get(callback))
+        .route("/auth/logout", get(logout))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn conf() -> AuthConfig {
+        AuthConfig {
+            base_url: "https://admin.example.com".to_string(),
+            admin_users: vec!["zhangsan".to_string()],
+            session_secret: "secret".to_string(),
+            session_hours: 12,
+        }
+    }
+
+    #[test]
+    fn test_session() {
+        let c = conf();
+        let token = session_token(&c.session_secret, "zhangsan", 1000);
+        assert_eq!(
+            verify_session(&c, &token, 999),
+            Some("zhangsan".to_string())
+        );
+        // 过期
+        assert_eq!(verify_session(&c, &token, 1001), None);
+        // 改了过期时间签名就不对了
+        let forged = token.replacen("1000", "9999", 1);
+        assert_eq!(verify_session(&c, &forged, 999), None);
+        // 换了密钥
+        let other = AuthConfig {
+            session_secret: "other".to_string(),
+            ..conf()
+        };
+        assert_eq!(verify_session(&other, &token, 999), None);
+        // 已经从名单里移除
+        let lisi = session_token(&c.session_secret, "lisi", 1000);
+        assert_eq!(verify_session(&c, &lisi, 999), None);
+        assert_eq!(verify_session(&c, "garbage", 999), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            format!("a=1; {}={}", SESSION_COOKIE, token)
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            session_user(&c, &headers, 999),
+            Some("zhangsan".to_string())
+        );
+        assert_eq!(session_user(&c, &HeaderMap::new(), 999), None);
+    }
+
+    #[test]
+    fn test_state() {
+        let state = oauth_state("secret", 1000);
+        assert!(verify_state("secret", &state, 1000));
+        assert!(verify_state("secret", &state, 1000 + STATE_TTL));
+        assert!(!verify_state("secret", &state, 1001 + STATE_TTL));
+        assert!(!verify_state("other", &state, 1000));
+        assert!(!verify_state("secret", "1000.abc", 1000));
+    }
+}
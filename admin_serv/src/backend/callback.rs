@@ -0,0 +1,250 @@
+use crate::backend::contact::ContactMap;
+use crate::backend::org::Orgs;
+use crate::state::{MemberScore, State};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use tracing::{info, instrument, warn};
+use wx::callback::{CallbackHandler, Event, Incoming, IncomingMsg, Reply};
+
+const HELP: &str = "支持的命令:
+今日排名
+我的分数 姓名
+开始学习
+未学习名单(管理员)
+刷新(管理员)
+补录 20231101 20231130(管理员)";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    TodayRank,
+    MyScore(Option<String>),
+    Inactive,
+    Refresh,
+    Backfill(Option<(NaiveDate, NaiveDate)>),
+    StartStudy,
+    Help,
+}
+
+impl Command {
+    fn parse(s: &str) -> Self {
+        let s = s.trim();
+        if let Some(name) = s.strip_prefix("我的分数") {
+            let name = name.trim();
+            return Command::MyScore((!name.is_empty()).then(|| name.to_string()));
+        }
+        if let Some(range) = s.strip_prefix("补录") {
+            return Command::Backfill(parse_range(range));
+        }
+        match s {
+            "今日排名" => Command::TodayRank,
+            "未学习名单" => Command::Inactive,
+            "刷新" => Command::Refresh,
+            "开始学习" => Command::StartStudy,
+            _ => Command::Help,
+        }
+    }
+}
+
+/// 最多补录一年
+const MAX_BACKFILL_DAYS: i64 = 366;
+
+fn parse_range(s: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let mut it = s
+        .split_whitespace()
+        .map(|d| NaiveDate::parse_from_str(d, "%Y%m%d"));
+    let (Some(Ok(start)), Some(Ok(end)), None) = (it.next(), it.next(), it.next()) else {
+        return None;
+    };
+    (start <= end && (end - start).num_days() < MAX_BACKFILL_DAYS).then_some((start, end))
+}
+
+/// 处理用户发给企业微信应用的消息，组织管理员操作自己的组织，
+/// 其他人按通讯录里的姓名查询自己所在的组织
+pub struct AppCallback {
+    orgs: Orgs,
+    contacts: ContactMap,
+    study_url: Option<String>,
+}
+
+impl AppCallback {
+    pub fn new(orgs: Orgs, contacts: ContactMap, study_url: Option<String>) -> Self {
+        Self {
+            orgs,
+            contacts,
+            study_url,
+        }
+    }
+
+    #[instrument(skip(self))]
+    fn reply_text(&self, user: &str, content: &str) -> Reply {
+        info!("收到应用消息: {}", content);
+        let cmd = Command::parse(content);
+        let name = match &cmd {
+            Command::MyScore(Some(name)) => Some(name.as_str()),
+            _ => self.contacts.name_of(user),
+        };
+        let org = self.orgs.for_user(user, name);
+        if matches!(
+            cmd,
+            Command::Inactive | Command::Refresh | Command::Backfill(_)
+        ) && user != org.admin_user
+        {
+            return Reply::Text("只有学习管理员可以使用这个命令".to_string());
+        }
+        let latest = || match org.history.latest() {
+            Ok(Some(s)) => Ok(s),
+            Ok(None) => Err("还没有积分数据，请管理员发送“刷新”".to_string()),
+            Err(e) => {
+                warn!("读取积分历史失败: {}", e);
+                Err("读取积分数据失败".to_string())
+            }
+        };
+        let text = match cmd {
+            Command::TodayRank => latest().map(|s| today_rank(&s, org.org_id)),
+            Command::MyScore(Some(name)) => latest().map(|s| my_score(&s, &name)),
+            Command::MyScore(None) => Ok("请发送“我的分数 姓名”".to_string()),
+            Command::Inactive => latest().map(|s| inactive_list(&s)),
+            // 被动回复要在 5 秒内返回，二维码都是之后主动发送
+            Command::Refresh => match org.ss.login_ticket() {
+                // 已经有人在扫码登录时不打断，重发当前的二维码
+                Some(ticket) => {
+                    org.ss.resend_login_qr(user, ticket);
+                    Ok("正在等待扫码登录，稍后重新发送当前的登录二维码".to_string())
+                }
+                None => {
+                    org.ss.relogin(user);
+                    Ok("正在打开学习强国后台，稍后发送登录二维码".to_string())
+                }
+            },
+            Command::Backfill(Some((start, end))) => {
+                org.ss.backfill(user, start, end);
+                Ok(format!(
+                    "开始补录 {} 到 {} 的积分，完成后通知你",
+                    start, end
+                ))
+            }
+            Command::Backfill(None) => Ok(format!(
+                "请发送“补录 开始日期 结束日期”，日期格式为 20231101，最多 {} 天",
+                MAX_BACKFILL_DAYS
+            )),
+            Command::StartStudy => match &self.study_url {
+                Some(url) => Ok(format!("<a href=\"{}\">点这里开始学习</a>", url)),
+                None => Ok("管理员还没有配置学习页面".to_string()),
+            },
+            Command::Help => Ok(HELP.to_string()),
+        };
+        Reply::Text(text.unwrap_or_else(|e| e))
+    }
+}
+
+fn sorted(score: &MemberScore) -> Vec<&crate::state::Member> {
+    let mut data = score.data.iter().collect::<Vec<_>>();
+    data.sort_by_key(|m| std::cmp::Reverse(m.range_real_score));
+    data
+}
+
+fn today_rank(score: &MemberScore, org_id: u64) -> String {
+    let mut r = format!("{} 学习积分排名", score.date);
+    if let Some(o) = score.organization_rank.iter().find(|a| a.org_id == org_id) {
+        r += &format!(
+            "\n{} 排名第 {} 名，平均分 {}",
+            o.org_name, o.rank, o.avg_score
+        );
+    }
+    for (i, m) in sorted(score).iter().take(10).enumerate() {
+        r += &format!("\n{}. {} {}", i + 1, m.user_name, m.range_real_score);
+    }
+    r
+}
+
+fn my_score(score: &MemberScore, name: &str) -> String {
+    let data = sorted(score);
+    match data.iter().position(|m| m.user_name == name) {
+        Some(i) => format!(
+            "{} {} 积分 {}，排名 {}/{}",
+            score.date,
+            name,
+            data[i].range_real_score,
+            i + 1,
+            data.len()
+        ),
+        None => format!("{} 没有找到 {} 的积分", score.date, name),
+    }
+}
+
+fn inactive_list(score: &MemberScore) -> String {
+    let names = score
+        .data
+        .iter()
+        .filter(|m| m.range_real_score < 1)
+        .map(|m| m.user_name.as_str())
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        return format!("{} 全员完成学习", score.date);
+    }
+    format!(
+        "{} {} 人未学习:\n{}",
+        score.date,
+        names.len(),
+        names.join("、")
+    )
+}
+
+#[async_trait]
+impl CallbackHandler for AppCallback {
+    async fn handle(&self, msg: Incoming) -> Option<Reply> {
+        match msg.msg {
+            IncomingMsg::Text { content, .. } => {
+                Some(self.reply_text(&msg.from_user_name, &content))
+            }
+            IncomingMsg::Event(Event::Click { key }) => {
+                Some(self.reply_text(&msg.from_user_name, &key))
+            }
+            IncomingMsg::Event(Event::EnterAgent) => Some(Reply::Text(HELP.to_string())),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::member;
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(Command::parse(" 今日排名 "), Command::TodayRank);
+        assert_eq!(
+            Command::parse("我的分数 张三"),
+            Command::MyScore(Some("张三".to_string()))
+        );
+        assert_eq!(Command::parse("我的分数"), Command::MyScore(None));
+        assert_eq!(Command::parse("你好"), Command::Help);
+        assert_eq!(
+            Command::parse("补录 20231101 20231130"),
+            Command::Backfill(Some((
+                NaiveDate::from_ymd_opt(2023, 11, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2023, 11, 30).unwrap()
+            )))
+        );
+        assert_eq!(
+            Command::parse("补录 20231130 20231101"),
+            Command::Backfill(None)
+        );
+        assert_eq!(Command::parse("补录 20231101"), Command::Backfill(None));
+
+        let s = MemberScore {
+            date: "20231201".to_string(),
+            count: 3,
+            data: vec![member("李四", 0), member("张三", 40), member("王五", 0)],
+            organization_rank: vec![],
+        };
+        assert_eq!(
+            today_rank(&s, 1),
+            "20231201 学习积分排名\n1. 张三 40\n2. 李四 0\n3. 王五 0"
+        );
+        assert_eq!(my_score(&s, "张三"), "20231201 张三 积分 40，排名 1/3");
+        assert_eq!(my_score(&s, "赵六"), "20231201 没有找到 赵六 的积分");
+        assert_eq!(inactive_list(&s), "20231201 2 人未学习:\n李四、王五");
+    }
+}
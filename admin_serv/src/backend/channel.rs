@@ -0,0 +1,173 @@
+mod dingtalk;
+mod email;
+mod feishu;
+mod webhook;
+
+use crate::backend::config::ChannelConfig;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use wx::{MsgApi, MP};
+
+pub use dingtalk::DingTalk;
+pub use email::Email;
+pub use feishu::Feishu;
+pub use webhook::Webhook;
+
+/// 通知渠道，企业微信之外的渠道不认识 `<font>` 标签时需要自行处理
+#[async_trait]
+pub trait Channel: Send + Sync {
+    fn name(&self) -> &str;
+    async fn send_markdown(&self, title: &str, msg: &str) -> Result<()>;
+    async fn send_text(&self, msg: &str) -> Result<()>;
+    async fn send_image(&self, img: &[u8]) -> Result<()>;
+    /// 不能直接发送图片的渠道返回 false，发通知时跳过图片
+    fn supports_image(&self) -> bool {
+        true
+    }
+}
+
+pub type Channels = Vec<Arc<dyn Channel>>;
+
+/// 按名称从 `[channels]` 配置构造渠道
+pub fn build_channels(
+    names: &[String],
+    conf: &HashMap<String, ChannelConfig>,
+    mp: &MP,
+) -> Result<Channels> {
+    names
+        .iter()
+        .map(|name| {
+            let c = conf
+                .get(name)
+                .ok_or(anyhow!("没有找到通知渠道配置: {}", name))?;
+            build_channel(name, c, mp)
+        })
+        .collect()
+}
+
+fn build_channel(name: &str, c: &ChannelConfig, mp: &MP) -> Result<Arc<dyn Channel>> {
+    let name = name.to_string();
+    Ok(match c.clone() {
+        ChannelConfig::WecomBot { api } => Arc::new(WecomBot {
+            name,
+            mp: mp.clone(),
+            api,
+        }),
+        ChannelConfig::WecomApp { to_user } => Arc::new(WecomApp {
+            name,
+            mp: mp.clone(),
+            to_user: to_user.join("|"),
+        }),
+        ChannelConfig::Dingtalk { webhook, secret } => {
+            Arc::new(DingTalk::new(name, webhook, secret))
+        }
+        ChannelConfig::Feishu { webhook, secret } => Arc::new(Feishu::new(name, webhook, secret)),
+        ChannelConfig::Email {
+            smtp_server,
+            smtp_port,
+            username,
+            password,
+            from,
+            to,
+        } => Arc::new(Email::new(
+            name,
+            &smtp_server,
+            smtp_port,
+            username,
+            password,
+            &from,
+            &to,
+        )?),
+        ChannelConfig::Webhook { url, headers } => Arc::new(Webhook::new(name, url, headers)),
+    })
+}
+
+/// 去掉企业微信 markdown 专有的 `<font color="...">` 标签
+pub fn strip_font_tags(msg: &str) -> String {
+    let mut r = String::with_capacity(msg.len());
+    let mut rest = msg;
+    while let Some(i) = rest.find("<font") {
+        r.push_str(&rest[..i]);
+        rest = match rest[i..].find('>') {
+            Some(j) => &rest[i + j + 1..],
+            None => "",
+        };
+    }
+    r.push_str(rest);
+    r.replace("</font>", "")
+}
+
+struct WecomBot {
+    name: String,
+    mp: MP,
+    api: String,
+}
+
+#[async_trait]
+impl Channel for WecomBot {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn send_markdown(&self, _title: &str, msg: &str) -> Result<()> {
+        self.mp.send_bot_msg(msg, &self.api).await
+    }
+    async fn send_text(&self, msg: &str) -> Result<()> {
+        self.mp.send_bot_text(msg, &self.api).await
+    }
+    async fn send_image(&self, img: &[u8]) -> Result<()> {
+        self.mp.send_bot_image(img, &self.api).await
+    }
+}
+
+struct WecomApp {
+    name: String,
+    mp: MP,
+    to_user: String,
+}
+
+#[async_trait]
+impl Channel for WecomApp {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn send_markdown(&self, _title: &str, msg: &str) -> Result<()> {
+        self.mp.send_markdown_msg(&self.to_user, msg).await?;
+        Ok(())
+    }
+    async fn send_text(&self, msg: &str) -> Result<()> {
+        self.mp.send_text_msg(&self.to_user, msg).await?;
+        Ok(())
+    }
+    async fn send_image(&self, img: &[u8]) -> Result<()> {
+        self.mp.send_image_msg(&self.to_user, img).await?;
+        Ok(())
+    }
+}
+
+fn hmac_sha256_base64(key: &[u8], msg: &[u8]) -> Result<String> {
+    use base64::Engine;
+    use hmac::{Hmac, Mac};
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(key)
+        .map_err(|e| anyhow!("初始化 hmac 失败: {}", e))?;
+    mac.update(msg);
+    Ok(base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strip_font_tags() {
+        assert_eq!(
+            strip_font_tags(
+                r#"**园区排名 <font color="info">2</font>名**, <font color="comment">落后1.5分</font>"#
+            ),
+            "**园区排名 2名**, 落后1.5分"
+        );
+        assert_eq!(strip_font_tags("没有标签"), "没有标签");
+        assert_eq!(strip_font_tags("坏掉的<font color="), "坏掉的");
+    }
+}
@@ -0,0 +1,130 @@
+use crate::backend::channel::{hmac_sha256_base64, strip_font_tags, Channel};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, instrument};
+
+/// 钉钉自定义机器人
+pub struct DingTalk {
+    name: String,
+    webhook: String,
+    secret: Option<String>,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+struct DingTalkResp {
+    errcode: i64,
+    errmsg: String,
+}
+
+impl DingTalk {
+    pub fn new(name: String, webhook: String, secret: Option<String>) -> Self {
+        Self {
+            name,
+            webhook,
+            secret,
+            client: Client::new(),
+        }
+    }
+
+    /// 加签: base64(hmac_sha256(secret, "{timestamp}\n{secret}")) 再做 url 编码
+    fn sign(secret: &str, timestamp: i64) -> Result<String> {
+        let s = hmac_sha256_base64(
+            secret.as_bytes(),
+            format!("{}\n{}", timestamp, secret).as_bytes(),
+        )?;
+        Ok(form_urlencoded::byte_serialize(s.as_bytes()).collect())
+    }
+
+    fn api(&self) -> Result<String> {
+        match &self.secret {
+            Some(secret) => {
+                let ts = chrono::Local::now().timestamp_millis();
+                Ok(format!(
+                    "{}&timestamp={}&sign={}",
+                    self.webhook,
+                    ts,
+                    Self::sign(secret, ts)?
+                ))
+            }
+            None => Ok(self.webhook.clone()),
+        }
+    }
+
+    #[instrument(skip_all, fields(name = %self.name))]
+    async fn post(&self, body: Value) -> Result<()> {
+        let resp = self.client.post(self.api()?).json(&body).send().await?;
+        let resp_status = resp.status();
+        let data_raw = resp.text().await?;
+        debug!("钉钉机器人返回 [{}]{}", resp_status, data_raw);
+        let data = serde_json::from_str::<DingTalkResp>(&data_raw).map_err(|e| {
+            anyhow!(
+                "钉钉机器人返回无法解析: {}: [{}]{}",
+                e,
+                resp_status,
+                data_raw
+            )
+        })?;
+        if data.errcode != 0 {
+            return Err(anyhow!(
+                "钉钉机器人发送失败 errcode: {}, errmsg: {}",
+                data.errcode,
+                data.errmsg
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Channel for DingTalk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send_markdown(&self, title: &str, msg: &str) -> Result<()> {
+        self.post(json!({
+            "msgtype": "markdown",
+            "markdown": {
+                "title": title,
+                "text": strip_font_tags(msg),
+            }
+        }))
+        .await
+    }
+
+    async fn send_text(&self, msg: &str) -> Result<()> {
+        self.post(json!({
+            "msgtype": "text",
+            "text": {
+                "content": msg
+            }
+        }))
+        .await
+    }
+
+    async fn send_image(&self, _img: &[u8]) -> Result<()> {
+        Err(anyhow!("钉钉机器人只能发送图片链接，不支持直接发送图片"))
+    }
+
+    fn supports_image(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sign() -> Result<()> {
+        assert_eq!(
+            DingTalk::sign("SECxxx", 1700000000000)?,
+            "plK5HYD7pW0AMQz3PBPzNXBlZe9ZIHa2a52gMYB3lHs%3D"
+        );
+        Ok(())
+    }
+}
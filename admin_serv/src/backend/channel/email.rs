@@ -0,0 +1,103 @@
+use crate::backend::channel::{strip_font_tags, Channel};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tracing::{debug, instrument};
+
+/// SMTP 邮件通知
+pub struct Email {
+    name: String,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl Email {
+    pub fn new(
+        name: String,
+        smtp_server: &str,
+        smtp_port: Option<u16>,
+        username: String,
+        password: String,
+        from: &str,
+        to: &[String],
+    ) -> Result<Self> {
+        let builder = AsyncSmtpTransport::<Tokio1Executor>::relay(smtp_server)
+            .map_err(|e| anyhow!("SMTP 服务器配置错误: {}", e))?
+            .credentials(Credentials::new(username, password));
+        let builder = match smtp_port {
+            Some(p) => builder.port(p),
+            None => builder,
+        };
+        Ok(Self {
+            name,
+            from: from
+                .parse()
+                .map_err(|e| anyhow!("发件人地址错误 {}: {}", from, e))?,
+            to: to
+                .iter()
+                .map(|s| {
+                    s.parse()
+                        .map_err(|e| anyhow!("收件人地址错误 {}: {}", s, e))
+                })
+                .collect::<Result<Vec<Mailbox>>>()?,
+            mailer: builder.build(),
+        })
+    }
+
+    fn message(&self, subject: &str) -> lettre::message::MessageBuilder {
+        let mut b = Message::builder().from(self.from.clone()).subject(subject);
+        for to in &self.to {
+            b = b.to(to.clone());
+        }
+        b
+    }
+
+    #[instrument(skip_all, fields(name = %self.name))]
+    async fn send(&self, m: Message) -> Result<()> {
+        let r = self
+            .mailer
+            .send(m)
+            .await
+            .map_err(|e| anyhow!("发送邮件失败: {}", e))?;
+        debug!("邮件发送结果: {:?}", r);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Channel for Email {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send_markdown(&self, title: &str, msg: &str) -> Result<()> {
+        let m = self
+            .message(title)
+            .header(ContentType::TEXT_PLAIN)
+            .body(strip_font_tags(msg))?;
+        self.send(m).await
+    }
+
+    async fn send_text(&self, msg: &str) -> Result<()> {
+        let m = self
+            .message("学习提醒")
+            .header(ContentType::TEXT_PLAIN)
+            .body(msg.to_string())?;
+        self.send(m).await
+    }
+
+    async fn send_image(&self, img: &[u8]) -> Result<()> {
+        let att = Attachment::new("notice.png".to_string())
+            .body(img.to_vec(), ContentType::parse("image/png")?);
+        let m = self.message("学习提醒").multipart(
+            MultiPart::mixed()
+                .singlepart(SinglePart::plain("请查看附件图片".to_string()))
+                .singlepart(att),
+        )?;
+        self.send(m).await
+    }
+}
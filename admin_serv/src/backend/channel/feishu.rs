@@ -0,0 +1,128 @@
+use crate::backend::channel::{hmac_sha256_base64, strip_font_tags, Channel};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, instrument};
+
+/// 飞书/Lark 自定义机器人
+pub struct Feishu {
+    name: String,
+    webhook: String,
+    secret: Option<String>,
+    client: Client,
+}
+
+#[derive(Deserialize, Debug)]
+struct FeishuResp {
+    #[serde(default)]
+    code: i64,
+    #[serde(default)]
+    msg: String,
+}
+
+impl Feishu {
+    pub fn new(name: String, webhook: String, secret: Option<String>) -> Self {
+        Self {
+            name,
+            webhook,
+            secret,
+            client: Client::new(),
+        }
+    }
+
+    /// 签名: base64(hmac_sha256("{timestamp}\n{secret}", ""))
+    fn sign(secret: &str, timestamp: i64) -> Result<String> {
+        hmac_sha256_base64(format!("{}\n{}", timestamp, secret).as_bytes(), b"")
+    }
+
+    #[instrument(skip_all, fields(name = %self.name))]
+    async fn post(&self, mut body: Value) -> Result<()> {
+        if let Some(secret) = &self.secret {
+            let ts = chrono::Local::now().timestamp();
+            body["timestamp"] = json!(ts.to_string());
+            body["sign"] = json!(Self::sign(secret, ts)?);
+        }
+        let resp = self.client.post(&self.webhook).json(&body).send().await?;
+        let resp_status = resp.status();
+        let data_raw = resp.text().await?;
+        debug!("飞书机器人返回 [{}]{}", resp_status, data_raw);
+        let data = serde_json::from_str::<FeishuResp>(&data_raw).map_err(|e| {
+            anyhow!(
+                "飞书机器人返回无法解析: {}: [{}]{}",
+                e,
+                resp_status,
+                data_raw
+            )
+        })?;
+        if data.code != 0 {
+            return Err(anyhow!(
+                "飞书机器人发送失败 code: {}, msg: {}",
+                data.code,
+                data.msg
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Channel for Feishu {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send_markdown(&self, title: &str, msg: &str) -> Result<()> {
+        self.post(json!({
+            "msg_type": "interactive",
+            "card": {
+                "header": {
+                    "title": {
+                        "tag": "plain_text",
+                        "content": title,
+                    }
+                },
+                "elements": [{
+                    "tag": "markdown",
+                    "content": strip_font_tags(msg),
+                }]
+            }
+        }))
+        .await
+    }
+
+    async fn send_text(&self, msg: &str) -> Result<()> {
+        self.post(json!({
+            "msg_type": "text",
+            "content": {
+                "text": msg
+            }
+        }))
+        .await
+    }
+
+    async fn send_image(&self, _img: &[u8]) -> Result<()> {
+        Err(anyhow!(
+            "飞书机器人需要先通过应用上传图片，不支持直接发送图片"
+        ))
+    }
+
+    fn supports_image(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sign() -> Result<()> {
+        assert_eq!(
+            Feishu::sign("xxx", 1700000000)?,
+            "gJHobRuB1K6ZXZdEF/vb+7Q7iF9ohP6WhlluBlJh3B4="
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use crate::backend::channel::Channel;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::Engine;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tracing::{debug, instrument};
+
+/// 通用 JSON webhook，请求体为
+/// `{"type": "markdown|text|image", "title": "", "content": "", "base64": ""}`
+pub struct Webhook {
+    name: String,
+    url: String,
+    headers: HashMap<String, String>,
+    client: Client,
+}
+
+impl Webhook {
+    pub fn new(name: String, url: String, headers: HashMap<String, String>) -> Self {
+        Self {
+            name,
+            url,
+            headers,
+            client: Client::new(),
+        }
+    }
+
+    #[instrument(skip_all, fields(name = %self.name))]
+    async fn post(&self, body: Value) -> Result<()> {
+        let mut req = self.client.post(&self.url).json(&body);
+        for (k, v) in &self.headers {
+            req = req.header(k, v);
+        }
+        let resp = req.send().await?;
+        let resp_status = resp.status();
+        let data_raw = resp.text().await?;
+        debug!("webhook 返回 [{}]{}", resp_status, data_raw);
+        if !resp_status.is_success() {
+            return Err(anyhow!("webhook 发送失败: [{}]{}", resp_status, data_raw));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Channel for Webhook {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send_markdown(&self, title: &str, msg: &str) -> Result<()> {
+        self.post(json!({
+            "type": "markdown",
+            "title": title,
+            "content": msg,
+        }))
+        .await
+    }
+
+    async fn send_text(&self, msg: &str) -> Result<()> {
+        self.post(json!({
+            "type": "text",
+            "content": msg,
+        }))
+        .await
+    }
+
+    async fn send_image(&self, img: &[u8]) -> Result<()> {
+        self.post(json!({
+            "type": "image",
+            "base64": base64::engine::general_purpose::STANDARD.encode(img),
+        }))
+        .await
+    }
+}
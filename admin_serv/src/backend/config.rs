@@ -1,4 +1,9 @@
+use crate::backend::export::ExportFormat;
+use crate::backend::scheduler::{daily_expr, monthly_expr, weekly_expr};
+use crate::backend::xxscore::period::Period;
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AdminConfig {
@@ -7,27 +12,498 @@ pub struct AdminConfig {
     pub admin_user: String,           // 学习管理员的企业微信ID
     pub notice_bot: Vec<String>,      // 企业微信群机器人 URL
     pub proxy_server: Option<String>, // 代理服务器地址
-    pub exec_hour: u32,               // 发送通知的小时
-    pub exec_minute: u32,             // 发送通知的分钟
+    pub exec_hour: u32,               // 每天定时抓取积分并发送日报的小时
+    pub exec_minute: u32,             // 每天定时抓取积分并发送日报的分钟
+    #[serde(default = "default_history_path")]
+    pub history_path: String, // 历史积分数据存储目录
 
     pub mp: MpConfig,
 
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
     pub notice_schedule: Vec<NoticeSchedule>,
+    #[serde(default)]
+    pub report_schedule: Vec<ReportSchedule>,
+    #[serde(default)]
+    pub report: ReportConfig,
+    #[serde(default)]
+    pub channels: HashMap<String, ChannelConfig>, // 企业微信以外的通知渠道，按名称引用
+    pub callback: Option<CallbackConfig>, // 企业微信应用接收消息的配置
+    pub auth: Option<AuthConfig>,         // 管理后台网页的企业微信登录，不配置时不校验
+    pub login: Option<LoginConfig>, // 保存学习强国后台的登录信息，不配置时每次定时抓取都要扫码
+    #[serde(default)]
+    pub contact: ContactConfig,
+    #[serde(default)]
+    pub orgs: Vec<OrgConfig>, // 其他分公司，顶层的 org_id 等配置作为默认组织
+}
+
+pub const DEFAULT_ORG: &str = "default";
+/// 登录 cookie 用 session_secret 签名，太短容易被猜出来伪造登录
+const MIN_SESSION_SECRET_LEN: usize = 32;
+
+/// 一个学习强国组织，各自抓取积分、保存历史和发送通知
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrgConfig {
+    pub name: String, // 组织名称，网页上切换组织和记录定时任务时使用
+    pub org_id: u64,
+    pub xx_org_gray_id: String,
+    pub admin_user: String,
+    pub notice_bot: Vec<String>,
+    pub exec_hour: u32,
+    pub exec_minute: u32,
+    pub history_path: Option<String>, // 默认为 {history_path}_{name}
+    #[serde(default)]
+    pub notice_schedule: Vec<NoticeSchedule>,
+    #[serde(default)]
+    pub report_schedule: Vec<ReportSchedule>,
+}
+
+impl AdminConfig {
+    /// 顶层配置作为默认组织排在第一个，后面是 `[[orgs]]`
+    pub fn all_orgs(&self) -> Vec<OrgConfig> {
+        let mut orgs = vec![OrgConfig {
+            name: DEFAULT_ORG.to_string(),
+            org_id: self.org_id,
+            xx_org_gray_id: self.xx_org_gray_id.clone(),
+            admin_user: self.admin_user.clone(),
+            notice_bot: self.notice_bot.clone(),
+            exec_hour: self.exec_hour,
+            exec_minute: self.exec_minute,
+            history_path: Some(self.history_path.clone()),
+            notice_schedule: self.notice_schedule.clone(),
+            report_schedule: self.report_schedule.clone(),
+        }];
+        orgs.extend(self.orgs.iter().map(|o| {
+            OrgConfig {
+                history_path: Some(
+                    o.history_path
+                        .clone()
+                        .unwrap_or_else(|| format!("{}_{}", self.history_path, o.name)),
+                ),
+                ..o.clone()
+            }
+        }));
+        orgs
+    }
+
+    /// 加载配置时检查，有误时拒绝启动或者继续使用原配置
+    pub fn validate(&self) -> Result<()> {
+        if let Some(a) = &self.auth {
+            if a.session_secret.len() < MIN_SESSION_SECRET_LEN {
+                return Err(anyhow!(
+                    "[auth] 的 session_secret 至少需要 {} 字节",
+                    MIN_SESSION_SECRET_LEN
+                ));
+            }
+        }
+        if let Some(l) = &self.login {
+            if l.cookie_secret.is_empty() {
+                return Err(anyhow!("[login] 的 cookie_secret 不能为空"));
+            }
+        }
+        let mut names = HashSet::new();
+        for o in &self.orgs {
+            if o.name == DEFAULT_ORG {
+                return Err(anyhow!(
+                    "{} 是默认组织的名称，[[orgs]] 不能使用",
+                    DEFAULT_ORG
+                ));
+            }
+            if !names.insert(o.name.as_str()) {
+                return Err(anyhow!("[[orgs]] 的名称 {} 重复了", o.name));
+            }
+        }
+        for o in self.all_orgs() {
+            // cookie 文件和 Chrome 用户目录按组织名称命名
+            if self.login.is_some()
+                && !o
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(anyhow!(
+                    "组织名称 {} 只能包含字母、数字、_ 和 -，用于保存登录信息",
+                    o.name
+                ));
+            }
+            for (i, x) in o.report_schedule.iter().enumerate() {
+                x.cron_expr().with_context(|| {
+                    format!("组织 {} 的第 {} 个 report_schedule 配置有误", o.name, i + 1)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 默认组织的定时任务沿用原来的名称，其他组织加上组织名称前缀
+    pub fn job_name(org: &str, job: &str) -> String {
+        if org == DEFAULT_ORG {
+            job.to_string()
+        } else {
+            format!("{}/{}", org, job)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ContactConfig {
+    pub sync: bool,                    // 启动时同步企业微信通讯录，按姓名自动匹配
+    pub override_path: Option<String>, // 手动指定 姓名 = UserID 的 toml 文件
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RemindMode {
+    Mention, // 在群机器人里 @ 未学习的人
+    Private, // 通过应用单独发消息
+    Both,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CallbackConfig {
+    pub token: String,            // 企业微信应用“接收消息”里的 Token
+    pub encoding_aes_key: String, // 企业微信应用“接收消息”里的 EncodingAESKey
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoginConfig {
+    pub cookie_dir: String,    // 加密保存登录 cookie 的目录，每个组织一个文件
+    pub cookie_secret: String, // 加密 cookie 的密钥
+    pub profile_dir: Option<String>, // 用保存的登录信息抓取时使用固定的 Chrome 用户目录，每个组织一个子目录
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuthConfig {
+    pub base_url: String, // 管理后台的外部访问地址，域名需要是企业微信应用的可信域名
+    pub admin_users: Vec<String>, // 允许登录管理后台的企业微信ID
+    pub session_secret: String, // 签名登录 cookie 的密钥，至少 32 字节
+    #[serde(default = "default_session_hours")]
+    pub session_hours: i64, // 登录有效期
+}
+
+fn default_session_hours() -> i64 {
+    12
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct MpConfig {
     pub proxy_server: Option<String>, // 代理服务器地址
     pub corp_id: String,
     pub corp_secret: String,
     pub agent_id: i64,
+    pub api_base: Option<String>, // 企业微信 API 地址，默认 https://qyapi.weixin.qq.com
+}
+
+impl MpConfig {
+    pub fn build(&self) -> anyhow::Result<wx::MP> {
+        let px = match &self.proxy_server {
+            Some(s) => Some(reqwest::Proxy::all(s)?),
+            None => None,
+        };
+        let mp = wx::MP::new(&self.corp_id, &self.corp_secret, self.agent_id, px);
+        Ok(match &self.api_base {
+            Some(api_base) => mp.with_api_base(api_base),
+            None => mp,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    pub timezone: String,             // 定时任务使用的时区
+    pub holiday_path: Option<String>, // 节假日和调休上班日期文件，workday_only 的任务会参考
+    pub catch_up_minutes: i64,        // 停机期间错过的任务，在这个时间内的启动后补发一次
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            timezone: "Asia/Shanghai".to_string(),
+            holiday_path: None,
+            catch_up_minutes: 120,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NoticeSchedule {
+    pub name: Option<String>, // 任务名称，用于记录上次执行时间，默认按顺序编号
+    #[serde(default)]
     pub hour: u32,
+    #[serde(default)]
     pub minute: u32,
+    pub cron: Option<String>, // 秒 分 时 日 月 周，设置后忽略 hour/minute
+    #[serde(default)]
+    pub workday_only: bool, // 只在工作日执行，节假日参考 holiday_path
     pub notice_bot: Option<Vec<String>>,
     pub notice_id: Option<Vec<String>>,
     pub text: Option<String>,
+    pub channels: Option<Vec<String>>, // 引用 [channels] 里的名称
+    pub remind_below: Option<u64>,     // 单独提醒昨天积分低于该值的人，需要通讯录映射
+    pub remind_text: Option<String>,   // 单独提醒的模板(minijinja)，可用 name/score/date/study_url
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReportSchedule {
+    pub name: Option<String>, // 任务名称，用于记录上次执行时间，默认按顺序编号
+    pub period: Period,       // week 或 month
+    #[serde(default)]
+    pub day: u32, // 周报为星期几(1-7)，月报为几号(1-28)
+    #[serde(default)]
+    pub hour: u32,
+    #[serde(default)]
+    pub minute: u32,
+    pub cron: Option<String>, // 秒 分 时 日 月 周，设置后忽略 day/hour/minute
+    #[serde(default)]
+    pub workday_only: bool,
+    pub notice_bot: Option<Vec<String>>,
+    pub notice_id: Option<Vec<String>>,
+    pub channels: Option<Vec<String>>, // 引用 [channels] 里的名称
+}
+
+impl NoticeSchedule {
+    pub fn cron_expr(&self) -> String {
+        self.cron
+            .clone()
+            .unwrap_or_else(|| daily_expr(self.hour, self.minute))
+    }
+}
+
+impl ReportSchedule {
+    pub fn cron_expr(&self) -> Result<String> {
+        match (&self.cron, &self.period) {
+            (Some(c), _) => Ok(c.clone()),
+            (None, Period::Week) => weekly_expr(self.day, self.hour, self.minute),
+            (None, Period::Month) => monthly_expr(self.day, self.hour, self.minute),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChannelConfig {
+    WecomBot {
+        api: String,
+    },
+    WecomApp {
+        to_user: Vec<String>,
+    },
+    Dingtalk {
+        webhook: String,
+        secret: Option<String>, // 加签密钥
+    },
+    Feishu {
+        webhook: String,
+        secret: Option<String>, // 签名校验密钥
+    },
+    Email {
+        smtp_server: String,
+        smtp_port: Option<u16>,
+        username: String,
+        password: String,
+        from: String,
+        to: Vec<String>,
+    },
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ReportConfig {
+    pub grind_score: u64,                       // 超过该分数算当日学霸
+    pub grind_limit: usize,                     // 学霸名单最多展示多少人
+    pub bands: Vec<ScoreBand>,                  // 管理员汇总里分数的颜色区间，按 below 升序
+    pub top_color: String,                      // 高于所有区间时的颜色
+    pub period_top: usize,                      // 周报/月报学霸展示多少人
+    pub zero_streak_days: usize,                // 连续多少天未学习会出现在周报/月报里
+    pub daily_template: Option<String>,         // 群机器人日报模板(minijinja)
+    pub admin_template: Option<String>,         // 管理员汇总模板(minijinja)
+    pub dept_template: Option<String>,          // 部门日报模板(minijinja)
+    pub dept_notice: Vec<DeptNotice>,           // 各部门单独通报的群机器人
+    pub channels: Vec<String>,                  // 日报额外发送的渠道，引用 [channels] 里的名称
+    pub study_url: Option<String>, // study_serv 学习页面地址，日报卡片的“去学习”按钮跳转到这里
+    pub card_to: Vec<String>,      // 以模板卡片形式接收日报的企业微信ID
+    pub remind_inactive: Option<RemindMode>, // 日报发出后提醒未学习的人，需要通讯录映射
+    pub remind_text: String,       // 提醒未学习的人的文字
+    pub attach: Option<ExportFormat>, // 管理员汇总同时以 csv/xlsx 文件发送完整积分表
+    pub leaderboard: Option<LeaderboardConfig>, // 群机器人日报后面再发一张排行榜图片
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LeaderboardConfig {
+    pub font_path: String, // 支持中文的 ttf/otf/ttc 字体文件，例如 NotoSansCJK
+    #[serde(default = "default_leaderboard_top")]
+    pub top: usize, // 排行榜展示多少人
+}
+
+fn default_leaderboard_top() -> usize {
+    20
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeptNotice {
+    pub dept_name: String,       // 与 Member.dept_names 中的部门名称一致
+    pub notice_bot: Vec<String>, // 企业微信群机器人 URL
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScoreBand {
+    pub below: u64,    // 分数低于该值时使用这个颜色
+    pub color: String, // 企业微信 markdown 的 info/comment/warning，空字符串表示不着色
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            grind_score: 34,
+            grind_limit: 20,
+            bands: vec![
+                ScoreBand {
+                    below: 25,
+                    color: "warning".to_string(),
+                },
+                ScoreBand {
+                    below: 35,
+                    color: "".to_string(),
+                },
+            ],
+            top_color: "info".to_string(),
+            period_top: 10,
+            zero_streak_days: 3,
+            daily_template: None,
+            admin_template: None,
+            dept_template: None,
+            dept_notice: vec![],
+            channels: vec![],
+            study_url: None,
+            card_to: vec![],
+            remind_inactive: None,
+            remind_text: "昨天没有学习强国的积分，今天记得学习哦".to_string(),
+            attach: None,
+            leaderboard: None,
+        }
+    }
+}
+
+impl ReportConfig {
+    pub fn color_of(&self, score: u64) -> &str {
+        self.bands
+            .iter()
+            .find(|b| score < b.below)
+            .map(|b| b.color.as_str())
+            .unwrap_or(&self.top_color)
+    }
+}
+
+fn default_history_path() -> String {
+    "./history".to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_all_orgs() -> Result<()> {
+        let conf: AdminConfig = toml::from_str(
+            r#"
+org_id = 1
+xx_org_gray_id = "gray1"
+admin_user = "admin"
+notice_bot = []
+exec_hour = 9
+exec_minute = 0
+notice_schedule = []
+
+[mp]
+corp_id = "corp"
+corp_secret = "secret"
+agent_id = 1
+
+[[orgs]]
+name = "branch"
+org_id = 2
+xx_org_gray_id = "gray2"
+admin_user = "admin2"
+notice_bot = ["bot2"]
+exec_hour = 10
+exec_minute = 30
+
+[[orgs.notice_schedule]]
+hour = 14
+minute = 0
+"#,
+        )?;
+        let orgs = conf.all_orgs();
+        assert_eq!(orgs.len(), 2);
+        assert_eq!(orgs[0].name, DEFAULT_ORG);
+        assert_eq!(orgs[0].history_path.as_deref(), Some("./history"));
+        assert_eq!(orgs[1].xx_org_gray_id, "gray2");
+        assert_eq!(orgs[1].history_path.as_deref(), Some("./history_branch"));
+        assert_eq!(orgs[1].notice_schedule.len(), 1);
+        assert_eq!(AdminConfig::job_name(DEFAULT_ORG, "notice_0"), "notice_0");
+        assert_eq!(
+            AdminConfig::job_name("branch", "notice_0"),
+            "branch/notice_0"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() -> Result<()> {
+        let base = r#"
+org_id = 1
+xx_org_gray_id = "gray1"
+admin_user = "admin"
+notice_bot = []
+exec_hour = 9
+exec_minute = 0
+notice_schedule = []
+
+[mp]
+corp_id = "corp"
+corp_secret = "secret"
+agent_id = 1
+"#;
+        let conf = |extra: &str| -> Result<AdminConfig> {
+            Ok(toml::from_str(&format!("{}{}", base, extra))?)
+        };
+        assert!(conf("")?.validate().is_ok());
+        let weekly = "[[report_schedule]]\nperiod = \"week\"\nday = 8\n";
+        assert!(conf(weekly)?.validate().is_err());
+        let monthly = "[[report_schedule]]\nperiod = \"month\"\nday = 31\n";
+        assert!(conf(monthly)?.validate().is_err());
+        let login = "[login]\ncookie_dir = \"./cookies\"\ncookie_secret = \"\"\n";
+        assert!(conf(login)?.validate().is_err());
+        let auth = |secret: &str| {
+            format!(
+                "[auth]\nbase_url = \"https://admin.example.com\"\nadmin_users = []\n\
+                 session_secret = \"{}\"\n",
+                secret
+            )
+        };
+        assert!(conf(&auth(""))?.validate().is_err());
+        assert!(conf(&auth("secret"))?.validate().is_err());
+        assert!(conf(&auth(&"s".repeat(32)))?.validate().is_ok());
+        let org = |name: &str| {
+            format!(
+                "[[orgs]]\nname = \"{}\"\norg_id = 2\nxx_org_gray_id = \"gray2\"\n\
+                 admin_user = \"admin2\"\nnotice_bot = []\nexec_hour = 9\nexec_minute = 0\n",
+                name
+            )
+        };
+        assert!(conf(&org("branch"))?.validate().is_ok());
+        assert!(conf(&org(DEFAULT_ORG))?.validate().is_err());
+        assert!(conf(&(org("branch") + &org("branch")))?.validate().is_err());
+        // 设置了 cron 时不看 day
+        let cron = "[[report_schedule]]\nperiod = \"month\"\ncron = \"0 0 9 1 * *\"\n";
+        assert!(conf(cron)?.validate().is_ok());
+        Ok(())
+    }
 }
@@ -0,0 +1,119 @@
+use crate::backend::config::ContactConfig;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use tracing::{info, instrument, warn};
+use wx::{DirectoryApi, DirectoryUser};
+
+/// 学习强国的 userName 到企业微信 UserID 的映射，
+/// 通讯录里同名的人无法自动匹配，需要写在覆盖文件里
+#[derive(Clone, Default, Debug)]
+pub struct ContactMap {
+    by_name: HashMap<String, String>,
+}
+
+impl ContactMap {
+    pub fn build(users: &[DirectoryUser], overrides: HashMap<String, String>) -> Self {
+        let mut by_name: HashMap<String, String> = HashMap::new();
+        let mut duplicated = vec![];
+        for u in users {
+            if by_name.insert(u.name.clone(), u.user_id.clone()).is_some() {
+                duplicated.push(u.name.clone());
+            }
+        }
+        for name in duplicated {
+            by_name.remove(&name);
+            if !overrides.contains_key(&name) {
+                warn!("通讯录里有多个 {}，请在覆盖文件里指定 UserID", name);
+            }
+        }
+        by_name.extend(overrides);
+        Self { by_name }
+    }
+
+    /// 覆盖文件为 toml，每行 `"姓名" = "UserID"`
+    pub fn load_overrides(path: &str) -> Result<HashMap<String, String>> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("读取通讯录覆盖文件 {} 失败: {}", path, e))?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    #[instrument(skip(api))]
+    pub async fn sync<T: DirectoryApi>(api: &T, conf: &ContactConfig) -> Result<Self> {
+        let overrides = match &conf.override_path {
+            Some(p) => Self::load_overrides(p)?,
+            None => HashMap::new(),
+        };
+        let users = if conf.sync {
+            api.all_users().await?
+        } else {
+            vec![]
+        };
+        let m = Self::build(&users, overrides);
+        info!("通讯录映射 {} 人", m.by_name.len());
+        Ok(m)
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<&str> {
+        self.by_name.get(name).map(|s| s.as_str())
+    }
+
+    /// UserID 对应的姓名
+    pub fn name_of(&self, user_id: &str) -> Option<&str> {
+        self.by_name
+            .iter()
+            .find(|(_, id)| *id == user_id)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// 返回 (找到的 UserID, 找不到的姓名)
+    pub fn resolve_all<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> (Vec<String>, Vec<&'a str>) {
+        let mut ids = vec![];
+        let mut missing = vec![];
+        for name in names {
+            match self.resolve(name) {
+                Some(id) => ids.push(id.to_string()),
+                None => missing.push(name),
+            }
+        }
+        (ids, missing)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn user(id: &str, name: &str) -> DirectoryUser {
+        DirectoryUser {
+            user_id: id.to_string(),
+            name: name.to_string(),
+            department: vec![1],
+        }
+    }
+
+    #[test]
+    fn test_build() {
+        let m = ContactMap::build(
+            &[
+                user("zhangsan", "张三"),
+                user("lisi", "李四"),
+                user("lisi2", "李四"),
+                user("wangwu", "王五"),
+            ],
+            HashMap::from([("王五".to_string(), "wangwu_old".to_string())]),
+        );
+        assert_eq!(m.resolve("张三"), Some("zhangsan"));
+        // 同名的不自动匹配
+        assert_eq!(m.resolve("李四"), None);
+        assert_eq!(m.resolve("王五"), Some("wangwu_old"));
+        assert_eq!(m.name_of("zhangsan"), Some("张三"));
+        assert_eq!(m.name_of("lisi"), None);
+
+        let (ids, missing) = m.resolve_all(["张三", "李四"]);
+        assert_eq!(ids, vec!["zhangsan"]);
+        assert_eq!(missing, vec!["李四"]);
+    }
+}
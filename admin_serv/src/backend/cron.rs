@@ -1,68 +1,365 @@
+use crate::backend::channel::build_channels;
 use crate::backend::config::AdminConfig;
-use crate::backend::push_notice::push_notice;
-use crate::backend::xxscore::{daily_score, get_yesterday};
-use anyhow::Result;
-use chrono::{Local, Timelike};
-use reqwest::Proxy;
-use std::time::Duration;
+use crate::backend::contact::ContactMap;
+use crate::backend::history::ScoreHistory;
+use crate::backend::org::Orgs;
+use crate::backend::push_notice::{push_notice, remind_members};
+use crate::backend::scheduler::{daily_expr, Calendar, Job};
+use crate::backend::xxscore::period::period_score;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::collections::HashSet;
+use std::future::Future;
+use std::time::{Duration, SystemTime};
 use tokio::fs;
 use tokio::time::interval;
 use tracing::{info, trace, warn};
+use wx::Outbox;
 
-pub async fn start_daily_notice(conf_path: &str) -> Result<()> {
-    let contents = fs::read_to_string(conf_path).await?;
-    let p: AdminConfig = toml::from_str(contents.as_str())?;
-    info!("通知任务定时任务已启动");
-    let mut ticker = interval(Duration::from_secs(60));
+/// 配置文件修改后才重新解析，解析失败时继续使用上一份配置
+struct ConfigLoader {
+    path: String,
+    modified: Option<SystemTime>,
+    conf: AdminConfig,
+    tz: Tz,
+    calendar: Calendar,
+}
+
+impl ConfigLoader {
+    async fn new(path: &str) -> Result<Self> {
+        let mut l = Self {
+            path: path.to_string(),
+            modified: None,
+            conf: toml::from_str(&fs::read_to_string(path).await?)?,
+            tz: Tz::UTC,
+            calendar: Calendar::default(),
+        };
+        l.apply()?;
+        l.modified = fs::metadata(path).await?.modified().ok();
+        Ok(l)
+    }
+
+    fn apply(&mut self) -> Result<()> {
+        self.conf.validate()?;
+        self.tz = self
+            .conf
+            .schedule
+            .timezone
+            .parse()
+            .map_err(|e| anyhow!("时区配置有误: {}", e))?;
+        self.calendar = match &self.conf.schedule.holiday_path {
+            Some(p) => Calendar::load(p)?,
+            None => Calendar::default(),
+        };
+        Ok(())
+    }
+
+    async fn reload(&mut self) {
+        let modified = match fs::metadata(&self.path).await {
+            Ok(m) => m.modified().ok(),
+            Err(e) => {
+                warn!("读取配置文件信息失败: {}", e);
+                return;
+            }
+        };
+        if modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        let conf: AdminConfig = match fs::read_to_string(&self.path)
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|s| Ok(toml::from_str(&s)?))
+        {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("重新加载配置文件失败，继续使用原配置: {}", e);
+                return;
+            }
+        };
+        let old = std::mem::replace(&mut self.conf, conf);
+        match self.apply() {
+            Ok(_) => info!("配置文件已重新加载"),
+            Err(e) => {
+                warn!("重新加载配置文件失败，继续使用原配置: {}", e);
+                self.conf = old;
+                _ = self.apply();
+            }
+        }
+    }
+}
 
-    let px = match p.mp.proxy_server {
-        Some(s) => Some(Proxy::all(s)?),
-        None => None,
+/// 根据上次检查时间判断任务是否需要执行，并记录本次检查时间。
+/// 第一次见到的任务只记录不执行，停机期间错过的任务在 catch_up 内补发一次
+fn check_due(
+    history: &ScoreHistory,
+    name: &str,
+    expr: &str,
+    workday_only: bool,
+    now: &DateTime<Tz>,
+    catch_up: chrono::Duration,
+    calendar: &Calendar,
+) -> Option<DateTime<Tz>> {
+    let job = match Job::new(expr, workday_only) {
+        Ok(j) => j,
+        Err(e) => {
+            warn!("任务 {} 配置有误: {}", name, e);
+            return None;
+        }
+    };
+    let last = match history.last_run(name) {
+        Ok(l) => l,
+        Err(e) => {
+            warn!("读取任务 {} 的执行记录失败: {}", name, e);
+            return None;
+        }
     };
-    let mp = wx::MP::new(&p.mp.corp_id, &p.mp.corp_secret, p.mp.agent_id, px);
+    if let Err(e) = history.set_last_run(name, now.timestamp()) {
+        warn!("保存任务 {} 的执行记录失败: {}", name, e);
+    }
+    let last = now.timezone().timestamp_opt(last?, 0).single()?;
+    let since = last.max(*now - catch_up);
+    let at = job.due(&since, now, calendar)?;
+    if *now - at > chrono::Duration::minutes(2) {
+        info!(job = name, "补发停机期间错过的任务，原定 {}", at);
+    }
+    Some(at)
+}
+
+/// 每个任务在自己的线程和 runtime 里执行，互不影响
+fn spawn_job<F>(f: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    std::thread::spawn(move || match tokio::runtime::Runtime::new() {
+        Ok(r) => r.block_on(f),
+        Err(e) => warn!("创建 tokio runtime 失败: {}", e),
+    });
+}
+
+pub async fn start_daily_notice(conf_path: &str, orgs: Orgs, contacts: ContactMap) -> Result<()> {
+    let mut loader = ConfigLoader::new(conf_path).await?;
+    info!("通知任务定时任务已启动");
+    let mut ticker = interval(Duration::from_secs(30));
+
+    let mut mp_conf = loader.conf.mp.clone();
+    let mut mp = mp_conf.build()?;
+    // 所有通知任务共用一个发送队列，群机器人限速才能生效
+    let mut outbox = Outbox::new(mp.clone());
+    // 新增的组织只提醒一次
+    let mut unknown_orgs = HashSet::new();
 
     loop {
         ticker.tick().await;
-        let d = Local::now();
-        trace!("每分钟定时任务检查 {}", d.format("%H:%M:%S"));
-        let tasks = {
-            let contents = fs::read_to_string(conf_path).await?;
-            let p: AdminConfig = toml::from_str(contents.as_str())?;
-            p.notice_schedule
-        };
+        loader.reload().await;
+        let conf = &loader.conf;
+        if conf.mp != mp_conf {
+            mp_conf = conf.mp.clone();
+            match mp_conf.build() {
+                Ok(m) => {
+                    info!("企业微信配置已更新");
+                    mp = m;
+                    outbox = Outbox::new(mp.clone());
+                }
+                Err(e) => warn!("企业微信配置有误，继续使用原配置: {}", e),
+            }
+        }
+        let now = Utc::now().with_timezone(&loader.tz);
+        let catch_up = chrono::Duration::minutes(conf.schedule.catch_up_minutes);
+        trace!("定时任务检查 {}", now.format("%H:%M:%S"));
 
-        for x in tasks {
-            if x.hour != d.hour() || x.minute != d.minute() {
+        for org_conf in conf.all_orgs() {
+            let Some(org) = orgs.get(&org_conf.name) else {
+                if unknown_orgs.insert(org_conf.name.clone()) {
+                    warn!("新增的组织 {} 需要重启后才能生效", org_conf.name);
+                }
                 continue;
+            };
+            let history = &org.history;
+            let job_name = |job: String| AdminConfig::job_name(&org.name, &job);
+
+            if check_due(
+                history,
+                &job_name("daily_scrape".to_string()),
+                &daily_expr(org_conf.exec_hour, org_conf.exec_minute),
+                false,
+                &now,
+                catch_up,
+                &loader.calendar,
+            )
+            .is_some()
+            {
+                let ss = org.ss.clone();
+                let name = org.name.clone();
+                spawn_job(async move {
+                    info!(org = name, "定时抓取学习强国积分");
+                    if let Err(e) = ss.scheduled_scrape().await {
+                        warn!(org = name, "定时抓取学习强国积分失败: {}", e);
+                    }
+                });
             }
-            let mp = mp.clone();
-            std::thread::spawn(move || {
-                let r = match tokio::runtime::Runtime::new() {
-                    Ok(r) => r,
+
+            for (i, x) in org_conf.notice_schedule.iter().enumerate() {
+                let name = job_name(x.name.clone().unwrap_or_else(|| format!("notice_{}", i)));
+                let Some(at) = check_due(
+                    history,
+                    &name,
+                    &x.cron_expr(),
+                    x.workday_only,
+                    &now,
+                    catch_up,
+                    &loader.calendar,
+                ) else {
+                    continue;
+                };
+                let channels = match build_channels(
+                    &x.channels.clone().unwrap_or_default(),
+                    &conf.channels,
+                    &mp,
+                ) {
+                    Ok(c) => c,
                     Err(e) => {
-                        warn!("创建 tokio runtime 失败: {}", e);
-                        return;
+                        warn!("初始化通知渠道失败: {}", e);
+                        continue;
                     }
                 };
-                r.block_on(async move {
-                    info!(hour = x.hour, minute = x.minute, "时间到了，通知大家搞学习");
+                let x = x.clone();
+                let outbox = outbox.clone();
+                let history = history.clone();
+                let contacts = contacts.clone();
+                let study_url = conf.report.study_url.clone();
+                spawn_job(async move {
+                    info!(job = name, %at, "时间到了，通知大家搞学习");
                     match push_notice(
-                        &mp,
+                        &outbox,
                         x.notice_id.clone(),
                         x.notice_bot.clone(),
                         x.text.clone(),
+                        channels,
                     )
                     .await
                     {
-                        Ok(_) => {
-                            info!("这一批通知发完了");
+                        Ok(reports) => {
+                            let failed = reports.iter().filter(|r| !r.is_sent()).count();
+                            info!(total = reports.len(), failed, "这一批通知发完了");
                         }
                         Err(e) => {
                             warn!("发送通知失败: {}", e);
                         }
                     }
+                    if let Some(below) = x.remind_below {
+                        match remind_members(
+                            &outbox,
+                            &history,
+                            &contacts,
+                            below,
+                            x.remind_text.as_deref(),
+                            study_url.as_deref(),
+                            at.date_naive(),
+                        )
+                        .await
+                        {
+                            Ok(reports) => {
+                                let failed = reports.iter().filter(|r| !r.is_sent()).count();
+                                info!(total = reports.len(), failed, "单独提醒发完了");
+                            }
+                            Err(e) => {
+                                warn!("单独提醒失败: {}", e);
+                            }
+                        }
+                    }
                 });
-            });
+            }
+
+            for (i, x) in org_conf.report_schedule.iter().enumerate() {
+                let name = job_name(x.name.clone().unwrap_or_else(|| format!("report_{}", i)));
+                let expr = match x.cron_expr() {
+                    Ok(e) => e,
+                    Err(e) => {
+                        warn!("任务 {} 配置有误: {}", name, e);
+                        continue;
+                    }
+                };
+                let Some(at) = check_due(
+                    history,
+                    &name,
+                    &expr,
+                    x.workday_only,
+                    &now,
+                    catch_up,
+                    &loader.calendar,
+                ) else {
+                    continue;
+                };
+                let channels = match build_channels(
+                    &x.channels.clone().unwrap_or_default(),
+                    &conf.channels,
+                    &mp,
+                ) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        warn!("初始化通知渠道失败: {}", e);
+                        continue;
+                    }
+                };
+                let x = x.clone();
+                let mp = mp.clone();
+                let history = history.clone();
+                let report_conf = conf.report.clone();
+                spawn_job(async move {
+                    info!(job = name, period = ?x.period, "发送学习积分汇总报告");
+                    match period_score(&history, &x, at.date_naive(), &report_conf, &channels, &mp)
+                        .await
+                    {
+                        Ok(_) => {
+                            info!("汇总报告发完了");
+                        }
+                        Err(e) => {
+                            warn!("发送汇总报告失败: {}", e);
+                        }
+                    }
+                });
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_due() -> Result<()> {
+        let history = ScoreHistory::temporary()?;
+        let tz: Tz = "Asia/Shanghai".parse().map_err(|e| anyhow!("{}", e))?;
+        let t = |s: &str| {
+            tz.from_local_datetime(
+                &chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap(),
+            )
+            .unwrap()
+        };
+        let cal = Calendar::default();
+        let check = |now: &str| {
+            check_due(
+                &history,
+                "notice_0",
+                "0 30 9 * * *",
+                false,
+                &t(now),
+                chrono::Duration::minutes(120),
+                &cal,
+            )
+        };
+        // 第一次只记录
+        assert_eq!(check("2024-02-08 09:30:10"), None);
+        assert_eq!(check("2024-02-09 09:29:40"), None);
+        assert_eq!(check("2024-02-09 09:30:10"), Some(t("2024-02-09 09:30:00")));
+        assert_eq!(check("2024-02-09 09:30:40"), None);
+        // 停机超过 catch_up 的不补发
+        assert_eq!(check("2024-02-10 12:00:00"), None);
+        // catch_up 内补发一次
+        assert_eq!(check("2024-02-11 10:30:00"), Some(t("2024-02-11 09:30:00")));
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use crate::backend::history::ScoreHistory;
+use crate::state::{Dashboard, DayStat, MemberScore, MemberTrend};
+use anyhow::Result;
+use chrono::{Duration, NaiveDate};
+use std::collections::HashMap;
+
+/// 看板最多展示的天数
+pub const MAX_DAYS: i64 = 366;
+
+/// 读取最近 days 天(以最后一次快照为准)的积分历史生成看板
+pub fn load(history: &ScoreHistory, org_id: u64, days: i64) -> Result<Dashboard> {
+    let Some(end) = history.dates()?.pop() else {
+        return Ok(Dashboard::default());
+    };
+    let start = match NaiveDate::parse_from_str(&end, "%Y%m%d") {
+        Ok(d) => (d - Duration::days(days.clamp(1, MAX_DAYS) - 1))
+            .format("%Y%m%d")
+            .to_string(),
+        Err(_) => end.clone(),
+    };
+    Ok(build(&history.range(&start, &end)?, org_id))
+}
+
+/// scores 需要按日期升序
+pub fn build(scores: &[MemberScore], org_id: u64) -> Dashboard {
+    let mut members: HashMap<u64, MemberTrend> = HashMap::new();
+    let mut days = vec![];
+    for (i, s) in scores.iter().enumerate() {
+        let total = s.data.len();
+        let active = s.data.iter().filter(|m| m.range_real_score > 0).count();
+        let sum: u64 = s.data.iter().map(|m| m.range_real_score).sum();
+        let org = s.organization_rank.iter().find(|o| o.org_id == org_id);
+        days.push(DayStat {
+            avg_score: sum as f64 / total.max(1) as f64,
+            completion_rate: active as f64 * 100.0 / total.max(1) as f64,
+            rank: org.map(|o| o.rank),
+            org_avg_score: org.map(|o| o.avg_score),
+        });
+        for m in &s.data {
+            let t = members.entry(m.user_id).or_insert_with(|| MemberTrend {
+                user_id: m.user_id,
+                scores: vec![None; scores.len()],
+                ..Default::default()
+            });
+            // 改名、调部门以最新的为准
+            t.name = m.user_name.clone();
+            t.dept_names = m.dept_names.clone();
+            t.scores[i] = Some(m.range_real_score);
+            t.total += m.range_real_score;
+        }
+    }
+    let mut members = members.into_values().collect::<Vec<_>>();
+    members.sort_by(|a, b| {
+        b.latest()
+            .cmp(&a.latest())
+            .then(b.total.cmp(&a.total))
+            .then(a.name.cmp(&b.name))
+    });
+    Dashboard {
+        dates: scores.iter().map(|s| s.date.clone()).collect(),
+        days,
+        members,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{Member, OrganizationRank};
+
+    fn member(id: u64, name: &str, score: u64) -> Member {
+        Member {
+            user_id: id,
+            dept_names: "办公室".to_string(),
+            ..crate::state::member(name, score)
+        }
+    }
+
+    fn score(date: &str, data: Vec<Member>, rank: u64) -> MemberScore {
+        MemberScore {
+            date: date.to_string(),
+            count: data.len() as i64,
+            data,
+            organization_rank: vec![OrganizationRank {
+                rank,
+                org_name: "园区".to_string(),
+                org_id: 1,
+                stat_date: date.to_string(),
+                avg_score: 20.0,
+                pre_diff_score: 0.0,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_dashboard() -> Result<()> {
+        let history = ScoreHistory::temporary()?;
+        assert_eq!(load(&history, 1, 30)?, Dashboard::default());
+
+        history.save(&score(
+            "20231130",
+            vec![member(1, "张三", 40), member(2, "李四", 0)],
+            3,
+        ))?;
+        history.save(&score(
+            "20231201",
+            vec![
+                member(1, "张三", 10),
+                member(2, "李四", 30),
+                member(3, "王五", 0),
+            ],
+            2,
+        ))?;
+        history.save(&score("20231101", vec![member(1, "张三", 5)], 9))?;
+
+        let d = load(&history, 1, 2)?;
+        assert_eq!(d.dates, vec!["20231130", "20231201"]);
+        assert_eq!(d.days[0].avg_score, 20.0);
+        assert_eq!(d.days[0].completion_rate, 50.0);
+        assert_eq!(d.days[1].rank, Some(2));
+        assert_eq!(d.days[1].org_avg_score, Some(20.0));
+        assert_eq!(
+            d.members
+                .iter()
+                .map(|m| (m.user_id, m.name.as_str(), m.scores.clone(), m.total))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, "李四", vec![Some(0), Some(30)], 30),
+                (1, "张三", vec![Some(40), Some(10)], 50),
+                (3, "王五", vec![None, Some(0)], 0),
+            ]
+        );
+        // 其他组织没有排名
+        assert_eq!(load(&history, 2, 2)?.days[1].rank, None);
+        assert_eq!(load(&history, 1, 400)?.dates.len(), 3);
+        Ok(())
+    }
+}
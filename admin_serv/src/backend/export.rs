@@ -0,0 +1,135 @@
+use crate::state::MemberScore;
+use anyhow::{anyhow, Result};
+use rust_xlsxwriter::{Format, Workbook};
+use serde::{Deserialize, Serialize};
+
+const HEADERS: [&str; 6] = ["日期", "姓名", "部门", "当日积分", "本月积分", "总积分"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    #[default]
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+
+    pub fn content_type(&self) -> &str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+        }
+    }
+}
+
+/// 每人每天一行，多天的快照按顺序排在一起
+pub fn export(scores: &[MemberScore], format: ExportFormat) -> Result<Vec<u8>> {
+    match format {
+        ExportFormat::Csv => to_csv(scores),
+        ExportFormat::Xlsx => to_xlsx(scores),
+    }
+}
+
+/// 文件名，例如 score_20231201.xlsx、score_20231201-20231231.csv
+pub fn file_name(scores: &[MemberScore], format: ExportFormat) -> String {
+    let dates = match (scores.first(), scores.last()) {
+        (Some(a), Some(b)) if a.date != b.date => format!("{}-{}", a.date, b.date),
+        (Some(a), _) => a.date.clone(),
+        _ => "empty".to_string(),
+    };
+    format!("score_{}.{}", dates, format.extension())
+}
+
+fn to_csv(scores: &[MemberScore]) -> Result<Vec<u8>> {
+    // 带上 BOM，Excel 打开时中文不会乱码
+    let mut w = csv::Writer::from_writer(b"\xEF\xBB\xBF".to_vec());
+    w.write_record(HEADERS)?;
+    for s in scores {
+        for m in &s.data {
+            w.write_record([
+                s.date.clone(),
+                m.user_name.clone(),
+                m.dept_names.clone(),
+                m.range_real_score.to_string(),
+                m.score_month.to_string(),
+                m.total_score.to_string(),
+            ])?;
+        }
+    }
+    w.into_inner().map_err(|e| anyhow!("生成 CSV 失败: {}", e))
+}
+
+fn to_xlsx(scores: &[MemberScore]) -> Result<Vec<u8>> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("学习积分")?;
+    let bold = Format::new().set_bold();
+    for (col, h) in HEADERS.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *h, &bold)?;
+    }
+    let mut row = 1;
+    for s in scores {
+        for m in &s.data {
+            sheet.write_string(row, 0, &s.date)?;
+            sheet.write_string(row, 1, &m.user_name)?;
+            sheet.write_string(row, 2, &m.dept_names)?;
+            sheet.write_number(row, 3, m.range_real_score as f64)?;
+            sheet.write_number(row, 4, m.score_month as f64)?;
+            sheet.write_number(row, 5, m.total_score as f64)?;
+            row += 1;
+        }
+    }
+    Ok(workbook.save_to_buffer()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{member, Member};
+
+    fn score(date: &str) -> MemberScore {
+        MemberScore {
+            date: date.to_string(),
+            count: 1,
+            data: vec![Member {
+                dept_names: "一部,二部".to_string(),
+                score_month: 300,
+                total_score: 9000,
+                ..member("张三", 35)
+            }],
+            organization_rank: vec![],
+        }
+    }
+
+    #[test]
+    fn test_export() -> Result<()> {
+        let scores = vec![score("20231201"), score("20231202")];
+        let csv = String::from_utf8(export(&scores, ExportFormat::Csv)?)?;
+        assert_eq!(
+            csv,
+            "\u{feff}日期,姓名,部门,当日积分,本月积分,总积分\n\
+             20231201,张三,\"一部,二部\",35,300,9000\n\
+             20231202,张三,\"一部,二部\",35,300,9000\n"
+        );
+        // xlsx 是 zip 文件
+        assert!(export(&scores, ExportFormat::Xlsx)?.starts_with(b"PK"));
+        assert_eq!(
+            file_name(&scores, ExportFormat::Csv),
+            "score_20231201-20231202.csv"
+        );
+        assert_eq!(
+            file_name(&scores[..1], ExportFormat::Xlsx),
+            "score_20231201.xlsx"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use crate::state::MemberScore;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::{info, instrument};
+
+/// 某一天日报的发送情况
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportStatus {
+    Pending, // 已经开始发送，进程重启后需要继续
+    Sent,
+    Failed, // 重试之后仍然失败，已经通知管理员，下次抓取到积分时继续发送
+}
+
+/// 每日积分快照的本地存储，以 `MemberScore.date`(%Y%m%d) 为 key，
+/// 同时记录定时任务的上次执行时间和每天日报的发送情况
+#[derive(Clone)]
+pub struct ScoreHistory {
+    scores: sled::Tree,
+    jobs: sled::Tree,
+    reports: sled::Tree,
+    steps: sled::Tree,
+}
+
+impl ScoreHistory {
+    pub fn open(path: &str) -> Result<Self> {
+        let db = sled::open(path).map_err(|e| anyhow!("打开历史数据库失败: {}", e))?;
+        info!("历史数据库已打开: {}", path);
+        Self::from_db(db)
+    }
+
+    #[cfg(test)]
+    pub fn temporary() -> Result<Self> {
+        Self::from_db(sled::Config::new().temporary(true).open()?)
+    }
+
+    fn from_db(db: sled::Db) -> Result<Self> {
+        let scores = db.open_tree("member_score")?;
+        let jobs = db.open_tree("job_last_run")?;
+        let reports = db.open_tree("daily_report")?;
+        let steps = db.open_tree("daily_report_step")?;
+        Ok(Self {
+            scores,
+            jobs,
+            reports,
+            steps,
+        })
+    }
+
+    #[instrument(skip_all, fields(date = %score.date))]
+    pub fn save(&self, score: &MemberScore) -> Result<()> {
+        let v = serde_json::to_vec(score)?;
+        self.scores.insert(score.date.as_bytes(), v)?;
+        self.scores.flush()?;
+        info!("保存积分快照 {} 条", score.data.len());
+        Ok(())
+    }
+
+    pub fn get(&self, date: &str) -> Result<Option<MemberScore>> {
+        match self.scores.get(date.as_bytes())? {
+            Some(v) => Ok(Some(serde_json::from_slice(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// 取 [start, end] 闭区间内的快照，按日期升序
+    pub fn range(&self, start: &str, end: &str) -> Result<Vec<MemberScore>> {
+        let mut r = vec![];
+        for x in self.scores.range(start.as_bytes()..=end.as_bytes()) {
+            let (_, v) = x?;
+            r.push(serde_json::from_slice(&v)?);
+        }
+        Ok(r)
+    }
+
+    pub fn latest(&self) -> Result<Option<MemberScore>> {
+        match self.scores.last()? {
+            Some((_, v)) => Ok(Some(serde_json::from_slice(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// 定时任务上次检查的时间戳(秒)
+    pub fn last_run(&self, job: &str) -> Result<Option<i64>> {
+        match self.jobs.get(job.as_bytes())? {
+            Some(v) => Ok(Some(i64::from_be_bytes(
+                v.as_ref()
+                    .try_into()
+                    .map_err(|_| anyhow!("任务 {} 的执行记录已损坏", job))?,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_last_run(&self, job: &str, ts: i64) -> Result<()> {
+        self.jobs.insert(job.as_bytes(), &ts.to_be_bytes())?;
+        self.jobs.flush()?;
+        Ok(())
+    }
+
+    /// date 的日报还没有处理过或者上次发送失败时标记为 Pending 并返回 true，
+    /// 发送中或已发送时返回 false，保证每天只发一次
+    pub fn claim_report(&self, date: &str) -> Result<bool> {
+        let pending = serde_json::to_vec(&ReportStatus::Pending)?;
+        let failed = serde_json::to_vec(&ReportStatus::Failed)?;
+        for old in [None, Some(failed)] {
+            let r = self
+                .reports
+                .compare_and_swap(date.as_bytes(), old, Some(pending.clone()))?;
+            if r.is_ok() {
+                self.reports.flush()?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn report_status(&self, date: &str) -> Result<Option<ReportStatus>> {
+        match self.reports.get(date.as_bytes())? {
+            Some(v) => Ok(Some(serde_json::from_slice(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_report_status(&self, date: &str, status: ReportStatus) -> Result<()> {
+        self.reports
+            .insert(date.as_bytes(), serde_json::to_vec(&status)?)?;
+        self.reports.flush()?;
+        Ok(())
+    }
+
+    /// date 的日报已经完成的步骤，重试时跳过
+    pub fn report_steps(&self, date: &str) -> Result<HashSet<String>> {
+        let prefix = format!("{}/", date);
+        let mut r = HashSet::new();
+        for k in self.steps.scan_prefix(prefix.as_bytes()).keys() {
+            r.insert(String::from_utf8(k?[prefix.len()..].to_vec())?);
+        }
+        Ok(r)
+    }
+
+    pub fn finish_report_step(&self, date: &str, step: &str) -> Result<()> {
+        self.steps
+            .insert(format!("{}/{}", date, step).as_bytes(), Vec::<u8>::new())?;
+        self.steps.flush()?;
+        Ok(())
+    }
+
+    /// 日报发送完成后不再需要每一步的记录
+    pub fn clear_report_steps(&self, date: &str) -> Result<()> {
+        for k in self
+            .steps
+            .scan_prefix(format!("{}/", date).as_bytes())
+            .keys()
+        {
+            self.steps.remove(k?)?;
+        }
+        self.steps.flush()?;
+        Ok(())
+    }
+
+    /// 开始发送但没有完成的日报，按日期升序
+    pub fn pending_reports(&self) -> Result<Vec<String>> {
+        let mut r = vec![];
+        for x in self.reports.iter() {
+            let (k, v) = x?;
+            if serde_json::from_slice::<ReportStatus>(&v)? == ReportStatus::Pending {
+                r.push(String::from_utf8(k.to_vec())?);
+            }
+        }
+        Ok(r)
+    }
+
+    pub fn dates(&self) -> Result<Vec<String>> {
+        let mut r = vec![];
+        for k in self.scores.iter().keys() {
+            r.push(String::from_utf8(k?.to_vec())?);
+        }
+        Ok(r)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn score(date: &str) -> MemberScore {
+        MemberScore {
+            date: date.to_string(),
+            count: 0,
+            data: vec![],
+            organization_rank: vec![],
+        }
+    }
+
+    #[test]
+    fn test_history() -> Result<()> {
+        let h = ScoreHistory::from_db(sled::Config::new().temporary(true).open()?)?;
+        for d in ["20231203", "20231201", "20231202", "20231205"] {
+            h.save(&score(d))?;
+        }
+        assert!(h.get("20231204")?.is_none());
+        assert_eq!(h.get("20231202")?.unwrap().date, "20231202");
+        assert_eq!(
+            h.range("20231202", "20231204")?
+                .iter()
+                .map(|s| s.date.as_str())
+                .collect::<Vec<_>>(),
+            vec!["20231202", "20231203"]
+        );
+        assert_eq!(h.latest()?.unwrap().date, "20231205");
+        assert_eq!(h.dates()?.len(), 4);
+
+        assert!(h.claim_report("20231202")?);
+        assert!(!h.claim_report("20231202")?);
+        assert!(h.claim_report("20231201")?);
+        assert_eq!(h.pending_reports()?, vec!["20231201", "20231202"]);
+        h.set_report_status("20231201", ReportStatus::Sent)?;
+        assert!(!h.claim_report("20231201")?);
+        assert_eq!(h.report_status("20231201")?, Some(ReportStatus::Sent));
+        assert_eq!(h.report_status("20231203")?, None);
+        assert_eq!(h.pending_reports()?, vec!["20231202"]);
+        // 失败的日报可以重新认领，继续发送没发完的部分
+        h.set_report_status("20231202", ReportStatus::Failed)?;
+        assert!(h.claim_report("20231202")?);
+        assert!(!h.claim_report("20231202")?);
+
+        h.finish_report_step("20231202", "bot/a")?;
+        h.finish_report_step("20231202", "card")?;
+        h.finish_report_step("20231203", "card")?;
+        assert_eq!(
+            h.report_steps("20231202")?,
+            HashSet::from(["bot/a".to_string(), "card".to_string()])
+        );
+        h.clear_report_steps("20231202")?;
+        assert!(h.report_steps("20231202")?.is_empty());
+        assert_eq!(h.report_steps("20231203")?.len(), 1);
+        Ok(())
+    }
+}
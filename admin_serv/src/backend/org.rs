@@ -0,0 +1,60 @@
+use crate::backend::history::ScoreHistory;
+use crate::backend::StateSession;
+use std::sync::Arc;
+use tracing::warn;
+
+/// 运行中的一个组织，每个组织有自己的学习强国后台会话和历史数据
+#[derive(Clone)]
+pub struct Org {
+    pub name: String,
+    pub org_id: u64,
+    pub admin_user: String,
+    pub ss: StateSession,
+    pub history: ScoreHistory,
+}
+
+/// 所有组织，第一个是默认组织
+#[derive(Clone)]
+pub struct Orgs(Arc<Vec<Org>>);
+
+impl Orgs {
+    pub fn new(orgs: Vec<Org>) -> Self {
+        assert!(!orgs.is_empty(), "至少需要一个组织");
+        Self(Arc::new(orgs))
+    }
+
+    pub fn default_org(&self) -> &Org {
+        &self.0[0]
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Org> {
+        self.0.iter().find(|o| o.name == name)
+    }
+
+    /// user 是哪个组织的管理员就用哪个组织，否则按姓名找最近一次积分里有这个人的组织，
+    /// 都找不到时用默认组织
+    pub fn for_user(&self, user: &str, name: Option<&str>) -> &Org {
+        if let Some(o) = self.0.iter().find(|o| o.admin_user == user) {
+            return o;
+        }
+        name.and_then(|name| {
+            self.0.iter().find(|o| match o.history.latest() {
+                Ok(Some(s)) => s.data.iter().any(|m| m.user_name == name),
+                Ok(None) => false,
+                Err(e) => {
+                    warn!(org = o.name, "读取积分历史失败: {}", e);
+                    false
+                }
+            })
+        })
+        .unwrap_or_else(|| self.default_org())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.0.iter().map(|o| o.name.clone()).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Org> {
+        self.0.iter()
+    }
+}
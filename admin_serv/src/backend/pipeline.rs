@@ -0,0 +1,271 @@
+use crate::backend::channel::Channels;
+use crate::backend::config::ReportConfig;
+use crate::backend::contact::ContactMap;
+use crate::backend::history::{ReportStatus, ScoreHistory};
+use crate::backend::xxscore::{daily_score, Leaderboard, Progress};
+use crate::state::MemberScore;
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{info, instrument, warn};
+use wx::{MsgApi, MP};
+
+/// 日报发送失败时的重试次数和间隔
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// 发送日报需要的配置，一个组织一份
+pub struct DailyReport {
+    pub mp: MP,
+    pub wechat_bots: Vec<String>,
+    pub org_id: u64,
+    pub admin_user: String,
+    pub history: ScoreHistory,
+    pub report: ReportConfig,
+    pub leaderboard: Option<Arc<Leaderboard>>,
+    pub channels: Channels,
+    pub contacts: ContactMap,
+}
+
+/// 抓取完成后把积分发到这里，由单独的线程保存快照并发送日报。
+/// 每天的日报在历史数据库里有发送记录，重复抓取只更新快照。
+/// 每一步发送成功后也会记下来，进程重启或者上次失败后只继续发送没发完的部分
+#[derive(Clone)]
+pub struct ReportPipeline {
+    tx: UnboundedSender<MemberScore>,
+}
+
+impl ReportPipeline {
+    pub fn start(daily: DailyReport) -> Self {
+        let (tx, rx) = unbounded_channel();
+        std::thread::spawn(move || match tokio::runtime::Runtime::new() {
+            Ok(r) => r.block_on(run(daily, rx, RETRY_DELAY)),
+            Err(e) => warn!("日报发送线程启动失败: {}", e),
+        });
+        Self { tx }
+    }
+
+    /// 没有发送线程，只用于测试抓取
+    #[cfg(test)]
+    pub fn detached() -> Self {
+        let (tx, _) = unbounded_channel();
+        Self { tx }
+    }
+
+    pub fn publish(&self, ms: MemberScore) {
+        if self.tx.send(ms).is_err() {
+            warn!("日报发送线程已经退出，积分没有保存");
+        }
+    }
+}
+
+async fn run(daily: DailyReport, mut rx: UnboundedReceiver<MemberScore>, delay: Duration) {
+    match daily.history.pending_reports() {
+        Ok(dates) => {
+            for date in dates {
+                info!(date, "继续发送上次没发完的日报");
+                deliver(&daily, &date, delay).await;
+            }
+        }
+        Err(e) => warn!("读取日报发送记录失败: {}", e),
+    }
+    while let Some(ms) = rx.recv().await {
+        if let Err(e) = daily.history.save(&ms) {
+            warn!("保存积分快照失败: {}", e);
+            continue;
+        }
+        match daily.history.claim_report(&ms.date) {
+            Ok(true) => deliver(&daily, &ms.date, delay).await,
+            Ok(false) => {
+                let text = match daily.history.report_status(&ms.date) {
+                    Ok(Some(ReportStatus::Sent)) => "已经发送过",
+                    Ok(Some(ReportStatus::Pending)) => "正在发送",
+                    r => {
+                        warn!(date = ms.date, "日报发送状态异常: {:?}", r);
+                        continue;
+                    }
+                };
+                info!(date = ms.date, "日报{}，只更新积分快照", text);
+                let text = format!("{} 的日报{}，这次只更新了积分数据", ms.date, text);
+                if let Err(e) = daily.mp.send_text_msg(&daily.admin_user, &text).await {
+                    warn!("通知管理员失败: {}", e);
+                }
+            }
+            Err(e) => warn!("记录日报发送状态失败: {}", e),
+        }
+    }
+}
+
+/// 发送成功的步骤保存到历史数据库，重试和下次继续发送时跳过
+struct Steps<'a> {
+    history: &'a ScoreHistory,
+    date: &'a str,
+    done: HashSet<String>,
+}
+
+impl Progress for Steps<'_> {
+    fn is_done(&self, step: &str) -> bool {
+        self.done.contains(step)
+    }
+
+    fn done(&mut self, step: &str) {
+        if let Err(e) = self.history.finish_report_step(self.date, step) {
+            warn!(step, "记录日报发送进度失败: {}", e);
+        }
+        self.done.insert(step.to_string());
+    }
+}
+
+/// 用保存的快照发送 date 的日报并记录结果
+#[instrument(skip(daily, delay))]
+async fn deliver(daily: &DailyReport, date: &str, delay: Duration) {
+    let done = match daily.history.report_steps(date) {
+        Ok(d) => d,
+        Err(e) => {
+            warn!("读取日报发送进度失败，从头发送: {}", e);
+            HashSet::new()
+        }
+    };
+    if !done.is_empty() {
+        info!(done = done.len(), "跳过已经发送的部分");
+    }
+    let mut steps = Steps {
+        history: &daily.history,
+        date,
+        done,
+    };
+    let mut attempt = 0;
+    let status = loop {
+        attempt += 1;
+        match send(daily, date, &mut steps).await {
+            Ok(_) => {
+                info!("日报发送完成");
+                if let Err(e) = daily.history.clear_report_steps(date) {
+                    warn!("清理日报发送进度失败: {}", e);
+                }
+                break ReportStatus::Sent;
+            }
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                warn!(attempt, "发送日报失败，稍后重试: {}", e);
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => {
+                warn!(attempt, "发送日报失败: {:#}", e);
+                let text = format!(
+                    "{} 的日报发送失败，下次抓取到积分时会继续发送没发完的部分: {:#}",
+                    date, e
+                );
+                if let Err(e) = daily.mp.send_text_msg(&daily.admin_user, &text).await {
+                    warn!("通知管理员失败: {}", e);
+                }
+                break ReportStatus::Failed;
+            }
+        }
+    };
+    if let Err(e) = daily.history.set_report_status(date, status) {
+        warn!("记录日报发送状态失败: {}", e);
+    }
+}
+
+async fn send(daily: &DailyReport, date: &str, steps: &mut Steps<'_>) -> Result<()> {
+    let ms = daily
+        .history
+        .get(date)?
+        .ok_or(anyhow!("没有 {} 的积分快照", date))?;
+    daily_score(ms, daily, steps).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wx::mock::MockServer;
+
+    fn score(date: &str) -> MemberScore {
+        MemberScore {
+            date: date.to_string(),
+            count: 0,
+            data: vec![],
+            organization_rank: vec![],
+        }
+    }
+
+    fn daily(server: &MockServer, history: &ScoreHistory) -> DailyReport {
+        DailyReport {
+            mp: server.mp(),
+            wechat_bots: vec![server.bot_url("org")],
+            org_id: 1,
+            admin_user: "admin".to_string(),
+            history: history.clone(),
+            report: ReportConfig::default(),
+            leaderboard: None,
+            channels: vec![],
+            contacts: ContactMap::default(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_pipeline() -> Result<()> {
+        let server = MockServer::start().await?;
+        let history = ScoreHistory::temporary()?;
+        // 上次发到一半退出了
+        history.save(&score("20231130"))?;
+        history.claim_report("20231130")?;
+        // 已经发送过
+        history.save(&score("20231129"))?;
+        history.claim_report("20231129")?;
+        history.set_report_status("20231129", ReportStatus::Sent)?;
+
+        // 补发的第一次失败
+        server.inject_errcode("/cgi-bin/webhook/send", 93000, "invalid webhook url");
+        let (tx, rx) = unbounded_channel();
+        tx.send(score("20231201"))?;
+        tx.send(score("20231201"))?;
+        tx.send(score("20231202"))?;
+        drop(tx);
+        run(daily(&server, &history), rx, Duration::ZERO).await;
+
+        // 20231130 重试后补发成功，20231201 只发一次，20231129 不再发送
+        let bot = server.requests_to("/cgi-bin/webhook/send");
+        assert_eq!(bot.len(), 4);
+        for d in ["20231130", "20231201", "20231202"] {
+            assert_eq!(history.report_status(d)?, Some(ReportStatus::Sent));
+        }
+        assert!(history.pending_reports()?.is_empty());
+        let sent = server.requests_to("/cgi-bin/message/send");
+        assert!(sent.iter().any(|r| r.json().unwrap()["text"]["content"]
+            .as_str()
+            .unwrap_or_default()
+            .contains("20231201 的日报已经发送过")));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_resume_failed() -> Result<()> {
+        let server = MockServer::start().await?;
+        let history = ScoreHistory::temporary()?;
+        // 上次群里已经发出去了，发给管理员时失败
+        history.save(&score("20231201"))?;
+        history.claim_report("20231201")?;
+        history.finish_report_step("20231201", &format!("bot/{}", server.bot_url("org")))?;
+        history.set_report_status("20231201", ReportStatus::Failed)?;
+
+        server.inject_errcode(
+            "/cgi-bin/message/send",
+            60020,
+            "not allow to access from your ip",
+        );
+        let (tx, rx) = unbounded_channel();
+        tx.send(score("20231201"))?;
+        drop(tx);
+        run(daily(&server, &history), rx, Duration::ZERO).await;
+
+        // 重新认领后只重试发给管理员，群里不会收到重复的日报
+        assert!(server.requests_to("/cgi-bin/webhook/send").is_empty());
+        assert_eq!(server.requests_to("/cgi-bin/message/send").len(), 2);
+        assert_eq!(history.report_status("20231201")?, Some(ReportStatus::Sent));
+        assert!(history.report_steps("20231201")?.is_empty());
+        Ok(())
+    }
+}
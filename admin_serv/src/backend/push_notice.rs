@@ -1,55 +1,263 @@
-use anyhow::Result;
-use tracing::instrument;
-use wx::{MsgApi, MP};
+use crate::backend::channel::Channels;
+use crate::backend::contact::ContactMap;
+use crate::backend::history::ScoreHistory;
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDate};
+use minijinja::{context, Environment};
+use tracing::{info, instrument, warn};
+use wx::{DeliveryReport, OutMsg, Outbox, Payload, Target};
 
-#[instrument(skip(mp))]
+const REMIND_TEMPLATE: &str =
+    "{{ name }}，你昨天({{ date }})的学习强国积分只有 {{ score }} 分，今天记得学习哦。\
+{% if study_url %}
+<a href=\"{{ study_url }}\">点这里开始学习</a>{% endif %}";
+
+/// 企业微信的消息走发送队列，某个接收方失败不影响其他接收方，返回每条消息的发送结果
+#[instrument(skip(outbox, channels))]
 pub async fn push_notice(
-    mp: &MP,
+    outbox: &Outbox,
     notice_id: Option<Vec<String>>,
     notice_bot: Option<Vec<String>>,
     notice_text: Option<String>,
-) -> Result<()> {
+    channels: Channels,
+) -> Result<Vec<DeliveryReport>> {
     let default_emoticon = include_bytes!("./notice.png");
+    let mut targets = vec![];
     if let Some(uids) = notice_id {
-        let to_user = uids.join("|");
-        mp.send_image_msg(&to_user, default_emoticon).await?;
+        targets.push(Target::User(uids.join("|")));
+    }
+    for bot_api in notice_bot.unwrap_or_default() {
+        targets.push(Target::Bot(bot_api));
+    }
+    let mut msgs = vec![];
+    for t in targets {
+        msgs.push(OutMsg::new(
+            t.clone(),
+            Payload::Image(default_emoticon.to_vec()),
+        ));
         if let Some(text) = notice_text.clone() {
-            mp.send_text_msg(&to_user, &text).await?;
+            msgs.push(OutMsg::new(t, Payload::Text(text)));
         }
     }
-    if let Some(bots) = notice_bot {
-        for bot_api in bots {
-            mp.send_bot_image(default_emoticon, &bot_api).await?;
-            if let Some(text) = notice_text.clone() {
-                mp.send_bot_text(&text, &bot_api).await?;
+    let reports = outbox.send_all(msgs).await;
+
+    for c in channels {
+        if c.supports_image() {
+            if let Err(e) = c.send_image(default_emoticon).await {
+                warn!("通知渠道 {} 发送图片失败: {}", c.name(), e);
+            }
+        }
+        if let Some(text) = notice_text.clone() {
+            if let Err(e) = c.send_text(&text).await {
+                warn!("通知渠道 {} 发送文本失败: {}", c.name(), e);
             }
         }
     }
-    Ok(())
+    Ok(reports)
+}
+
+/// 按昨天的积分，单独提醒低于 below 分的人，每人收到的文字里有自己的分数。
+/// 学习强国后台只能查到昨天的积分，没有昨天的快照时不提醒，免得拿旧数据打扰别人
+#[instrument(skip(outbox, history, contacts, template))]
+pub async fn remind_members(
+    outbox: &Outbox,
+    history: &ScoreHistory,
+    contacts: &ContactMap,
+    below: u64,
+    template: Option<&str>,
+    study_url: Option<&str>,
+    today: NaiveDate,
+) -> Result<Vec<DeliveryReport>> {
+    let yesterday = (today - Duration::days(1)).format("%Y%m%d").to_string();
+    let score = history
+        .get(&yesterday)?
+        .ok_or(anyhow!("还没有 {} 的积分数据，跳过提醒", yesterday))?;
+    let mut env = Environment::new();
+    env.add_template("remind", template.unwrap_or(REMIND_TEMPLATE))
+        .map_err(|e| anyhow!("提醒模板有误: {}", e))?;
+    let tmpl = env.get_template("remind")?;
+
+    let mut msgs = vec![];
+    for m in score.data.iter().filter(|m| m.range_real_score < below) {
+        let Some(user_id) = contacts.resolve(&m.user_name) else {
+            warn!("{} 没有对应的企业微信账号，无法提醒", m.user_name);
+            continue;
+        };
+        let text = tmpl
+            .render(context! {
+                name => m.user_name,
+                score => m.range_real_score,
+                date => score.date,
+                study_url => study_url,
+            })
+            .map_err(|e| anyhow!("渲染提醒失败: {}", e))?;
+        msgs.push(OutMsg::new(
+            Target::User(user_id.to_string()),
+            Payload::Text(text),
+        ));
+    }
+    info!("{} 人需要单独提醒", msgs.len());
+    Ok(outbox.send_all(msgs).await)
 }
 
 #[cfg(test)]
 mod test {
-    use crate::backend::config::AdminConfig;
     use super::*;
+    use crate::state::member;
+    use wx::mock::MockServer;
 
     #[tokio::test]
     async fn test_push_notice() -> Result<()> {
-        let p: AdminConfig = toml::from_str(include_str!("../../config.toml"))?;
+        let server = MockServer::start().await?;
+        let outbox = Outbox::new(server.mp());
+
+        let reports = push_notice(
+            &outbox,
+            Some(vec!["UserID1".to_string(), "UserID2".to_string()]),
+            Some(vec![server.bot_url("a"), server.bot_url("b")]),
+            Some("学不动了，来点士力架吧".to_string()),
+            vec![],
+        )
+        .await?;
+        assert_eq!(reports.len(), 6);
+        assert!(reports.iter().all(|r| r.is_sent()));
+
+        let sent = server.requests_to("/cgi-bin/message/send");
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].json()?["touser"], "UserID1|UserID2");
+        assert_eq!(sent[0].json()?["msgtype"], "image");
+        assert_eq!(sent[1].json()?["text"]["content"], "学不动了，来点士力架吧");
+
+        let bot = server.requests_to("/cgi-bin/webhook/send");
+        assert_eq!(bot.len(), 4);
+        for key in ["a", "b"] {
+            let b = bot
+                .iter()
+                .filter(|r| r.query["key"] == key)
+                .collect::<Vec<_>>();
+            assert_eq!(b.len(), 2);
+            assert_eq!(b[0].json()?["msgtype"], "image");
+            assert_eq!(b[1].json()?["msgtype"], "text");
+        }
+        Ok(())
+    }
 
-        let mp = wx::MP::new(&p.corp_id, &p.corp_secret, p.agent_id);
+    /// 只能发文字的渠道，记录收到的消息类型
+    #[derive(Default)]
+    struct TextOnly(std::sync::Mutex<Vec<&'static str>>);
 
-        for x in p.notice_schedule {
-            push_notice(
-                &mp,
-                x.notice_id,
-                x.notice_bot,
-                None,
-                // Some("学不动了，来点士力架吧".to_string()),
-            )
-            .await?;
+    #[async_trait::async_trait]
+    impl crate::backend::channel::Channel for TextOnly {
+        fn name(&self) -> &str {
+            "text_only"
         }
+        async fn send_markdown(&self, _title: &str, _msg: &str) -> Result<()> {
+            self.0.lock().unwrap().push("markdown");
+            Ok(())
+        }
+        async fn send_text(&self, _msg: &str) -> Result<()> {
+            self.0.lock().unwrap().push("text");
+            Ok(())
+        }
+        async fn send_image(&self, _img: &[u8]) -> Result<()> {
+            self.0.lock().unwrap().push("image");
+            Err(anyhow!("不支持发送图片"))
+        }
+        fn supports_image(&self) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn test_push_notice_skip_image() -> Result<()> {
+        let server = MockServer::start().await?;
+        let c = std::sync::Arc::new(TextOnly::default());
+        push_notice(
+            &Outbox::new(server.mp()),
+            None,
+            None,
+            Some("该学习了".to_string()),
+            vec![c.clone()],
+        )
+        .await?;
+        assert_eq!(*c.0.lock().unwrap(), vec!["text"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remind_members() -> Result<()> {
+        let server = MockServer::start().await?;
+        let outbox = Outbox::new(server.mp());
+        let history = ScoreHistory::temporary()?;
+        history.save(&crate::state::MemberScore {
+            date: "20231201".to_string(),
+            count: 3,
+            data: vec![member("张三", 40), member("李四", 5), member("王五", 0)],
+            organization_rank: vec![],
+        })?;
+        let contacts = ContactMap::build(
+            &[],
+            [("张三", "zhangsan"), ("李四", "lisi")]
+                .into_iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect(),
+        );
+
+        let today = NaiveDate::from_ymd_opt(2023, 12, 2).unwrap();
+        let reports = remind_members(
+            &outbox,
+            &history,
+            &contacts,
+            10,
+            None,
+            Some("https://example.com/study"),
+            today,
+        )
+        .await?;
+        // 王五没有企业微信账号
+        assert_eq!(reports.len(), 1);
+        let sent = server.requests_to("/cgi-bin/message/send");
+        assert_eq!(sent.len(), 1);
+        let body = sent[0].json()?;
+        assert_eq!(body["touser"], "lisi");
+        assert_eq!(
+            body["text"]["content"],
+            "李四，你昨天(20231201)的学习强国积分只有 5 分，今天记得学习哦。\n<a href=\"https://example.com/study\">点这里开始学习</a>"
+        );
+
+        // 最近的快照不是昨天的，不提醒
+        let later = NaiveDate::from_ymd_opt(2023, 12, 3).unwrap();
+        assert!(
+            remind_members(&outbox, &history, &contacts, 10, None, None, later)
+                .await
+                .is_err()
+        );
+        assert_eq!(server.requests_to("/cgi-bin/message/send").len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_push_notice_partial_failure() -> Result<()> {
+        let server = MockServer::start().await?;
+        let outbox = Outbox::new(server.mp());
+        server.inject_errcode(
+            "/cgi-bin/message/send",
+            81013,
+            "user & party & tag all invalid",
+        );
 
+        let reports = push_notice(
+            &outbox,
+            Some(vec!["nobody".to_string()]),
+            Some(vec![server.bot_url("a")]),
+            Some("学不动了，来点士力架吧".to_string()),
+            vec![],
+        )
+        .await?;
+        let failed = reports.iter().filter(|r| !r.is_sent()).collect::<Vec<_>>();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].target, Target::User("nobody".to_string()));
+        assert_eq!(server.requests_to("/cgi-bin/webhook/send").len(), 2);
         Ok(())
     }
 }
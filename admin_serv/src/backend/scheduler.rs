@@ -0,0 +1,191 @@
+use ::cron::Schedule;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, NaiveDate, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// 节假日文件，日期格式为 %Y-%m-%d
+///
+/// ```toml
+/// holidays = ["2024-02-12", "2024-02-13"] # 法定节假日
+/// workdays = ["2024-02-04", "2024-02-18"] # 调休上班的周末
+/// ```
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Calendar {
+    #[serde(default)]
+    holidays: HashSet<String>,
+    #[serde(default)]
+    workdays: HashSet<String>,
+}
+
+impl Calendar {
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("读取节假日文件 {} 失败: {}", path, e))?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    pub fn is_workday(&self, d: NaiveDate) -> bool {
+        let key = d.format("%Y-%m-%d").to_string();
+        if self.workdays.contains(&key) {
+            return true;
+        }
+        if self.holidays.contains(&key) {
+            return false;
+        }
+        !matches!(d.weekday(), Weekday::Sat | Weekday::Sun)
+    }
+}
+
+pub struct Job {
+    schedule: Schedule,
+    workday_only: bool,
+}
+
+impl Job {
+    /// expr 为 秒 分 时 日 月 周[ 年]，周可以用 MON-SUN
+    pub fn new(expr: &str, workday_only: bool) -> Result<Self> {
+        let schedule =
+            Schedule::from_str(expr).map_err(|e| anyhow!("cron 表达式 {} 有误: {}", expr, e))?;
+        Ok(Self {
+            schedule,
+            workday_only,
+        })
+    }
+
+    /// (since, now] 之间最近一次应该执行的时间，多次错过只执行一次
+    pub fn due(
+        &self,
+        since: &DateTime<Tz>,
+        now: &DateTime<Tz>,
+        calendar: &Calendar,
+    ) -> Option<DateTime<Tz>> {
+        let mut r = None;
+        for t in self.schedule.after(since) {
+            if t > *now {
+                break;
+            }
+            if !self.workday_only || calendar.is_workday(t.date_naive()) {
+                r = Some(t);
+            }
+        }
+        r
+    }
+}
+
+pub fn daily_expr(hour: u32, minute: u32) -> String {
+    format!("0 {} {} * * *", minute, hour)
+}
+
+/// day 为 1-7，对应周一到周日
+pub fn weekly_expr(day: u32, hour: u32, minute: u32) -> Result<String> {
+    let day = ["MON", "TUE", "WED", "THU", "FRI", "SAT", "SUN"]
+        .get(day.wrapping_sub(1) as usize)
+        .ok_or(anyhow!("周报的 day 应为 1-7，实际为 {}", day))?;
+    Ok(format!("0 {} {} * * {}", minute, hour, day))
+}
+
+/// day 为 1-28，29 号以后小月没有这一天，整月都不会执行
+pub fn monthly_expr(day: u32, hour: u32, minute: u32) -> Result<String> {
+    if !(1..=28).contains(&day) {
+        return Err(anyhow!(
+            "月报的 day 应为 1-28，实际为 {}，29 号以后小月不会执行",
+            day
+        ));
+    }
+    Ok(format!("0 {} {} {} * *", minute, hour, day))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(tz: Tz, d: &str) -> DateTime<Tz> {
+        tz.from_local_datetime(
+            &chrono::NaiveDateTime::parse_from_str(d, "%Y-%m-%d %H:%M:%S").unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_due() -> Result<()> {
+        let tz: Tz = "Asia/Shanghai".parse().map_err(|e| anyhow!("{}", e))?;
+        let cal: Calendar = toml::from_str(
+            r#"
+holidays = ["2024-02-12"]
+workdays = ["2024-02-18"]
+"#,
+        )?;
+        let job = Job::new(&daily_expr(9, 30), true)?;
+
+        // 正点
+        assert_eq!(
+            job.due(
+                &at(tz, "2024-02-08 09:29:30"),
+                &at(tz, "2024-02-08 09:30:10"),
+                &cal
+            ),
+            Some(at(tz, "2024-02-08 09:30:00"))
+        );
+        // 还没到
+        assert_eq!(
+            job.due(
+                &at(tz, "2024-02-08 09:00:00"),
+                &at(tz, "2024-02-08 09:29:59"),
+                &cal
+            ),
+            None
+        );
+        // 停机两天，只补最近一次
+        assert_eq!(
+            job.due(
+                &at(tz, "2024-02-06 10:00:00"),
+                &at(tz, "2024-02-08 12:00:00"),
+                &cal
+            ),
+            Some(at(tz, "2024-02-08 09:30:00"))
+        );
+        // 节假日和周末跳过，调休上班的周日执行
+        assert!(!cal.is_workday(NaiveDate::from_ymd_opt(2024, 2, 12).unwrap()));
+        assert!(cal.is_workday(NaiveDate::from_ymd_opt(2024, 2, 18).unwrap()));
+        assert_eq!(
+            job.due(
+                &at(tz, "2024-02-12 00:00:00"),
+                &at(tz, "2024-02-12 23:00:00"),
+                &cal
+            ),
+            None
+        );
+        assert!(job
+            .due(
+                &at(tz, "2024-02-18 00:00:00"),
+                &at(tz, "2024-02-18 23:00:00"),
+                &cal
+            )
+            .is_some());
+
+        let week = Job::new(&weekly_expr(1, 9, 0)?, false)?;
+        assert_eq!(
+            week.due(
+                &at(tz, "2024-02-06 00:00:00"),
+                &at(tz, "2024-02-13 00:00:00"),
+                &cal
+            ),
+            Some(at(tz, "2024-02-12 09:00:00"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_expr_day() {
+        assert_eq!(weekly_expr(7, 9, 0).unwrap(), "0 0 9 * * SUN");
+        assert!(weekly_expr(0, 9, 0).is_err());
+        assert!(weekly_expr(8, 9, 0).is_err());
+        assert_eq!(monthly_expr(28, 9, 30).unwrap(), "0 30 9 28 * *");
+        assert!(monthly_expr(0, 9, 0).is_err());
+        assert!(monthly_expr(31, 9, 0).is_err());
+    }
+}
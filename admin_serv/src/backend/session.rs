@@ -1,12 +1,31 @@
-use crate::backend::xxscore::{daily_score, XxAdmin};
+use crate::backend::channel::Channels;
+use crate::backend::config::{OrgConfig, ReportConfig};
+use crate::backend::contact::ContactMap;
+use crate::backend::history::ScoreHistory;
+use crate::backend::pipeline::{DailyReport, ReportPipeline};
+use crate::backend::xxscore::fetcher::{backfill_with_cookies, scrape_with_cookies, AdminLogin};
+use crate::backend::xxscore::{Leaderboard, XxAdmin};
 use crate::state::State;
 use anyhow::Result;
+use chrono::NaiveDate;
+use qrcode_generator::QrCodeEcc;
 use std::collections::HashMap;
 use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use study_core::utils::UserValidator;
-use tracing::instrument;
-use wx::MP;
+use tracing::{info, instrument, warn};
+use wx::{MsgApi, MP};
+
+/// 一个组织的会话用到的存储和通知服务
+pub struct Services {
+    pub history: ScoreHistory,
+    pub login: AdminLogin,
+    pub report: ReportConfig,
+    pub leaderboard: Option<Arc<Leaderboard>>,
+    pub channels: Channels,
+    pub contacts: ContactMap,
+}
 
 #[derive(Clone)]
 pub struct StateSession {
@@ -15,57 +34,122 @@ pub struct StateSession {
     xx_org_gray_id: String,
     proxy_server: Option<String>,
 
-    wechat_bots: Vec<String>,
-    org_id: u64,
     admin_user: String,
+    history: ScoreHistory,
+    login: AdminLogin,
+    pipeline: ReportPipeline,
 }
 
 impl StateSession {
     pub fn new(
         mp: MP,
-        xx_org_gray_id: &str,
         proxy_server: Option<String>,
-        wechat_bots: Vec<String>,
-        org_id: u64,
-        admin_user: String,
+        org: &OrgConfig,
+        services: Services,
     ) -> Result<Self> {
+        let Services {
+            history,
+            login,
+            report,
+            leaderboard,
+            channels,
+            contacts,
+        } = services;
+        let pipeline = ReportPipeline::start(DailyReport {
+            mp: mp.clone(),
+            wechat_bots: org.notice_bot.clone(),
+            org_id: org.org_id,
+            admin_user: org.admin_user.clone(),
+            history: history.clone(),
+            report,
+            leaderboard,
+            channels,
+            contacts,
+        });
         Ok(Self {
             data: Arc::new(RwLock::new(XxAdmin::new(
-                xx_org_gray_id,
+                &org.xx_org_gray_id,
                 proxy_server.clone(),
+                login.clone(),
+                pipeline.clone(),
             )?)),
             mp,
-            xx_org_gray_id: xx_org_gray_id.to_string(),
-            proxy_server: proxy_server.clone(),
-            wechat_bots,
-            org_id,
-            admin_user,
+            xx_org_gray_id: org.xx_org_gray_id.clone(),
+            proxy_server,
+            admin_user: org.admin_user.clone(),
+            history,
+            login,
+            pipeline,
         })
     }
     #[instrument(skip_all, level = "trace")]
     fn renew(&self) -> Result<()> {
-        let xx = XxAdmin::new(&self.xx_org_gray_id, self.proxy_server.clone())?;
+        let xx = XxAdmin::new(
+            &self.xx_org_gray_id,
+            self.proxy_server.clone(),
+            self.login.clone(),
+            self.pipeline.clone(),
+        )?;
         let mut d = self.data.write().unwrap();
         *d = xx;
         Ok(())
     }
 
+    /// 放弃当前的登录，重新打开学习强国后台
+    pub fn refresh(&self) -> Result<()> {
+        self.renew()
+    }
+
+    /// 正在等待扫码并且二维码还没过期时返回当前的登录 ticket
+    pub fn login_ticket(&self) -> Option<String> {
+        match self.data.read().unwrap().get_state() {
+            State::WaitingLogin((ticket, expired_at))
+                if expired_at > chrono::Local::now().timestamp() =>
+            {
+                Some(ticket)
+            }
+            _ => None,
+        }
+    }
+
+    /// 把当前的登录二维码再发给 user
+    pub fn resend_login_qr(&self, user: &str, ticket: String) {
+        let mp = self.mp.clone();
+        let user = user.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = send_qr(&mp, &user, &ticket).await {
+                warn!("发送登录二维码失败: {}", e);
+            }
+        });
+    }
+
+    /// 在后台重新打开学习强国后台，二维码出来之后发给 user，不阻塞调用方
+    pub fn relogin(&self, user: &str) {
+        let ss = self.clone();
+        let user = user.to_string();
+        tokio::spawn(async move {
+            let r = {
+                let ss = ss.clone();
+                tokio::task::spawn_blocking(move || ss.refresh()).await
+            };
+            match r {
+                Ok(Ok(_)) => ss.send_login_qr(&user),
+                Ok(Err(e)) => {
+                    warn!("重新打开学习强国后台失败: {}", e);
+                    _ = ss.mp.send_text_msg(&user, "打开学习强国后台失败").await;
+                }
+                Err(e) => warn!("重新打开学习强国后台失败: {}", e),
+            }
+        });
+    }
+
+    /// 只读取状态，抓取完成后的日报由 ReportPipeline 发送
     #[instrument(skip_all, level = "trace")]
     pub async fn get(&self) -> Result<State> {
         let s = {
             let data = self.data.read().unwrap();
             data.get_state()
         };
-        if let State::Complete(ms) = s.clone() {
-            daily_score(
-                ms,
-                self.wechat_bots.clone(),
-                self.org_id,
-                &self.admin_user,
-                &self.mp,
-            )
-            .await?;
-        }
 
         match s.clone() {
             State::Broken(_) => self.renew()?,
@@ -75,4 +159,130 @@ impl StateSession {
 
         Ok(s)
     }
+
+    /// 定时抓取积分，保存的登录信息失效时才给管理员发送登录二维码，
+    /// 网络等其他原因失败时返回错误，不打扰管理员扫码
+    #[instrument(skip_all)]
+    pub async fn scheduled_scrape(&self) -> Result<()> {
+        let scraped =
+            scrape_with_cookies(&self.xx_org_gray_id, &self.proxy_server, &self.login).await?;
+        if let Some(ms) = scraped {
+            info!("使用保存的登录信息抓取积分成功");
+            self.pipeline.publish(ms);
+            return Ok(());
+        }
+        self.refresh()?;
+        self.mp
+            .send_text_msg(&self.admin_user, "学习强国后台登录已失效，请扫码重新登录")
+            .await?;
+        self.send_login_qr(&self.admin_user);
+        Ok(())
+    }
+
+    /// 用保存的登录信息补录 [start, end] 的历史积分，只保存不通知，完成后告诉 user。
+    /// 已经有快照的日期跳过，避免没有组织排名的补录数据覆盖定时抓取的快照
+    pub fn backfill(&self, user: &str, start: NaiveDate, end: NaiveDate) {
+        let ss = self.clone();
+        let user = user.to_string();
+        std::thread::spawn(move || {
+            let r = match tokio::runtime::Runtime::new() {
+                Ok(r) => r,
+                Err(e) => {
+                    warn!("创建 tokio runtime 失败: {}", e);
+                    return;
+                }
+            };
+            r.block_on(async move {
+                let text = match ss.backfill_missing(start, end).await {
+                    Ok(text) => text,
+                    Err(e) => {
+                        warn!("补录历史积分失败: {}", e);
+                        format!("补录失败: {}", e)
+                    }
+                };
+                if let Err(e) = ss.mp.send_text_msg(&user, &text).await {
+                    warn!("发送补录结果失败: {}", e);
+                }
+            });
+        });
+    }
+
+    async fn backfill_missing(&self, start: NaiveDate, end: NaiveDate) -> Result<String> {
+        let mut dates = vec![];
+        for d in start.iter_days().take_while(|d| *d <= end) {
+            let date = d.format("%Y%m%d").to_string();
+            if self.history.get(&date)?.is_none() {
+                dates.push(date);
+            }
+        }
+        if dates.is_empty() {
+            return Ok("这段时间的积分都已经有了，不需要补录".to_string());
+        }
+        let mut saved = 0;
+        let r = backfill_with_cookies(
+            &self.xx_org_gray_id,
+            &self.proxy_server,
+            &self.login,
+            &dates,
+            |ms| match self.history.save(&ms) {
+                Ok(_) => saved += 1,
+                Err(e) => warn!("保存 {} 的积分快照失败: {}", ms.date, e),
+            },
+        )
+        .await;
+        Ok(match r {
+            Ok(Some(_)) => format!("补录完成，共 {}/{} 天", saved, dates.len()),
+            Ok(None) => "学习强国后台登录已失效，请先发送“刷新”扫码登录".to_string(),
+            Err(e) => {
+                warn!("补录历史积分中断: {}", e);
+                format!("补录中断，已保存 {}/{} 天: {}", saved, dates.len(), e)
+            }
+        })
+    }
+
+    /// 二维码出来之后发给 user；扫码后继续轮询，这样不打开网页也能完成统计和通知
+    pub fn send_login_qr(&self, user: &str) {
+        let ss = self.clone();
+        let user = user.to_string();
+        tokio::spawn(async move {
+            let mut sent = false;
+            // XxAdmin 200 秒后失效
+            for _ in 0..100 {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                match ss.get().await {
+                    Ok(State::WaitingLogin((ticket, _))) if !sent => {
+                        sent = true;
+                        if let Err(e) = send_qr(&ss.mp, &user, &ticket).await {
+                            warn!("发送登录二维码失败: {}", e);
+                            return;
+                        }
+                    }
+                    Ok(State::Complete(_)) => {
+                        info!("扫码登录后的统计完成");
+                        return;
+                    }
+                    Ok(State::Broken(e)) => {
+                        _ = ss
+                            .mp
+                            .send_text_msg(&user, &format!("学习强国后台异常: {}", e))
+                            .await;
+                        return;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("获取学习强国后台状态失败: {}", e);
+                        return;
+                    }
+                }
+            }
+        });
+    }
+}
+
+async fn send_qr(mp: &MP, user: &str, ticket: &str) -> Result<()> {
+    let png = qrcode_generator::to_png_to_vec(ticket, QrCodeEcc::Low, 320)?;
+    mp.send_image_msg(user, &png).await?;
+    mp.send_text_msg(user, "请用学习强国扫码登录管理后台")
+        .await?;
+    Ok(())
 }
@@ -1,25 +1,87 @@
+use crate::backend::config::LoginConfig;
 use crate::backend::xxscore::get_yesterday;
 use crate::state::{MemberScore, StateChange};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use headless_chrome::browser::default_executable;
-use headless_chrome::protocol::cdp::Page;
+use headless_chrome::protocol::cdp::{Network, Page};
 use headless_chrome::{browser, Browser, LaunchOptions, Tab};
 use serde::{Deserialize, Serialize};
 use std::ops::Add;
+use std::path::PathBuf;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
 use std::time::Duration;
+use study_core::cookie::CookieStore;
 use study_core::decode_qr;
-use study_core::utils::{get_one_tab, new_browser, reset_tabs, Chrome};
+use study_core::utils::{
+    get_one_tab, new_browser, new_browser_with_profile, reset_tabs, Chrome, ChromeBrowser,
+};
 use tracing::{debug, error, info, instrument, trace, warn};
 use wx::{drop_msg_task, DropMsg, MsgApi, MP};
 
-#[instrument(skip(tx, proxy_server))]
+const ADMIN_URL: &str = "https://study.xuexi.cn/admin";
+
+/// 一个组织的学习强国后台登录信息，cookie 以组织名称为 key 加密保存，
+/// 没有配置 [login] 时不保存，定时抓取每次都需要扫码
+#[derive(Clone)]
+pub struct AdminLogin {
+    org: String,
+    store: Option<Arc<CookieStore>>,
+    profile_dir: Option<PathBuf>,
+}
+
+impl AdminLogin {
+    pub fn new(org: &str, conf: Option<&LoginConfig>) -> Result<Self> {
+        let store = match conf {
+            Some(c) => Some(Arc::new(CookieStore::new(&c.cookie_dir, &c.cookie_secret)?)),
+            None => None,
+        };
+        Ok(Self {
+            org: org.to_string(),
+            store,
+            profile_dir: conf
+                .and_then(|c| c.profile_dir.as_ref())
+                .map(|d| PathBuf::from(d).join(org)),
+        })
+    }
+
+    /// 用保存的登录信息抓取时使用的浏览器，配置了 profile_dir 时使用固定的用户目录
+    fn browser(&self, proxy_server: &Option<String>) -> Result<ChromeBrowser> {
+        new_browser_with_profile(proxy_server.clone(), self.profile_dir.clone())
+    }
+
+    fn cookies(&self) -> Result<Option<Vec<Network::Cookie>>> {
+        match &self.store {
+            Some(s) => s.load(&self.org),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, browser: &ChromeBrowser) -> Result<()> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        let cookies = browser.export_cookies()?;
+        store.save(&self.org, &cookies)?;
+        info!(org = self.org, "已保存学习强国后台登录信息");
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        match &self.store {
+            Some(s) => s.remove(&self.org),
+            None => Ok(()),
+        }
+    }
+}
+
+#[instrument(skip(tx, proxy_server, login))]
 pub async fn browse_xx_admin(
     tx: Sender<StateChange>,
     xx_org_gray_id: &str,
     proxy_server: &Option<String>,
+    login: &AdminLogin,
 ) -> Result<MemberScore> {
     let browser = new_browser(proxy_server.clone())?;
     tx.send(StateChange::Init)?;
@@ -39,23 +101,127 @@ pub async fn browse_xx_admin(
     let tab = browser
         .get_tabs()?
         .iter()
-        .find(|t| t.get_url().contains("https://study.xuexi.cn/admin"))
+        .find(|t| t.get_url().contains(ADMIN_URL))
         .cloned()
         .ok_or(anyhow!("没找到管理员界面标签"))?;
+    if let Err(e) = login.save(&browser) {
+        warn!("保存学习强国后台登录信息失败: {}", e);
+    }
 
     let date = get_yesterday();
+    fetch_score(&tab, xx_org_gray_id, &date, &date, true).await
+}
+
+/// 用保存的 cookie 打开学习强国后台抓取积分，登录已失效时返回 None
+#[instrument(skip(proxy_server, login))]
+pub async fn scrape_with_cookies(
+    xx_org_gray_id: &str,
+    proxy_server: &Option<String>,
+    login: &AdminLogin,
+) -> Result<Option<MemberScore>> {
+    let browser = login.browser(proxy_server)?;
+    let Some(tab) = open_with_cookies(&browser, login)? else {
+        return Ok(None);
+    };
+    let date = get_yesterday();
+    let score = fetch_score(&tab, xx_org_gray_id, &date, &date, true).await?;
+    // 每次使用后更新，尽量延长登录的有效期
+    if let Err(e) = login.save(&browser) {
+        warn!("保存学习强国后台登录信息失败: {}", e);
+    }
+    Ok(Some(score))
+}
+
+/// 用保存的 cookie 逐日抓取 dates(%Y%m%d) 的积分，用于补录历史数据。
+/// 每抓到一天就交给 save，中途失败时已经抓到的不会丢
+#[instrument(skip(proxy_server, login, save))]
+pub async fn backfill_with_cookies(
+    xx_org_gray_id: &str,
+    proxy_server: &Option<String>,
+    login: &AdminLogin,
+    dates: &[String],
+    mut save: impl FnMut(MemberScore),
+) -> Result<Option<()>> {
+    let browser = login.browser(proxy_server)?;
+    let Some(tab) = open_with_cookies(&browser, login)? else {
+        return Ok(None);
+    };
+    for date in dates {
+        let score = fetch_score(&tab, xx_org_gray_id, date, date, false).await?;
+        info!("抓取 {} 的积分 {} 条", date, score.data.len());
+        save(score);
+    }
+    Ok(Some(()))
+}
+
+fn open_with_cookies(browser: &ChromeBrowser, login: &AdminLogin) -> Result<Option<Arc<Tab>>> {
+    let Some(cookies) = login.cookies()? else {
+        info!("没有保存的登录信息");
+        return Ok(None);
+    };
+    reset_tabs(browser)?;
+    let tab = get_one_tab(browser)?;
+    browser.import_cookies(&cookies)?;
+    tab.navigate_to(ADMIN_URL)
+        .map_err(|e| anyhow!("打开学习强国后台失败: {}", e))?;
+    tab.wait_until_navigated()?;
+    if tab
+        .wait_for_element_with_custom_timeout(".userName", Duration::from_secs(20))
+        .is_ok()
+    {
+        return Ok(Some(tab));
+    }
+    // 登录失效时会跳转到登录页，还停在后台页面说明只是加载慢，保留 cookie 下次再试
+    let url = tab.get_url();
+    if is_login_expired(&url) {
+        info!(url, "保存的登录信息已失效");
+        login.clear()?;
+        return Ok(None);
+    }
+    Err(anyhow!("打开学习强国后台超时: {}", url))
+}
+
+fn is_login_expired(url: &str) -> bool {
+    !url.starts_with(ADMIN_URL) || url.contains("login")
+}
+
+/// start/end 为 %Y%m%d，相同时 MemberScore.date 就是这一天
+async fn fetch_score(
+    tab: &Arc<Tab>,
+    xx_org_gray_id: &str,
+    start: &str,
+    end: &str,
+    with_rank: bool,
+) -> Result<MemberScore> {
     // Run JavaScript in the page
-    let yesterday_js = include_str!("yesterday_score.js");
+    let member_js = include_str!("member_score.js");
     let body = tab
         .wait_for_element("body")
         .map_err(|e| anyhow!("获取执行 js 的DOM: {}", e))?;
     let remote_object = body
-        .call_js_fn(yesterday_js, vec![date.into(), xx_org_gray_id.into()], true)
+        .call_js_fn(
+            member_js,
+            vec![
+                start.into(),
+                end.into(),
+                xx_org_gray_id.into(),
+                with_rank.into(),
+            ],
+            true,
+        )
         .map_err(|e| anyhow!("执行js脚本失败: {}", e))?;
     let score_result = match remote_object.value {
         Some(serde_json::Value::String(returned_string)) => {
             let v = serde_json::from_str::<MemberScore>(&returned_string)
                 .map_err(|e| anyhow!("解析学习强国分数失败: {}: {}", e, returned_string))?;
+            if v.missing() > 0 {
+                warn!(
+                    "{} 的积分数据不完整，共 {} 人，只拿到 {} 人",
+                    v.date,
+                    v.count,
+                    v.data.len()
+                );
+            }
             Ok(v)
         }
         Some(v) => {
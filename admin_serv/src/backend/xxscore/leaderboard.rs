@@ -0,0 +1,262 @@
+use crate::backend::config::LeaderboardConfig;
+use crate::state::MemberScore;
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use anyhow::{anyhow, Result};
+use tiny_skia::{Color, Paint, Pixmap, PremultipliedColorU8, Rect, Transform};
+
+const WIDTH: u32 = 720;
+const HEADER: f32 = 130.0;
+const ROW: f32 = 44.0;
+const PADDING: f32 = 32.0;
+
+/// 图片上要画的内容，和字体无关，方便测试
+#[derive(Debug, PartialEq)]
+struct Board {
+    title: String,
+    subtitle: String,
+    rows: Vec<(String, u64)>,
+    max: u64,
+}
+
+fn board(score: &MemberScore, org_id: u64, top: usize) -> Board {
+    let mut data = score.data.iter().collect::<Vec<_>>();
+    data.sort_by_key(|m| std::cmp::Reverse(m.range_real_score));
+    let active = data.iter().filter(|m| m.range_real_score > 0).count();
+    let mut subtitle = format!(
+        "完成率 {:.1}%  {}/{} 人",
+        active as f64 * 100.0 / data.len().max(1) as f64,
+        active,
+        data.len()
+    );
+    if let Some(o) = score.organization_rank.iter().find(|a| a.org_id == org_id) {
+        subtitle = format!(
+            "{} 第 {} 名  平均分 {}  {}",
+            o.org_name, o.rank, o.avg_score, subtitle
+        );
+    }
+    let rows = data
+        .iter()
+        .take(top)
+        .map(|m| (m.user_name.clone(), m.range_real_score))
+        .collect::<Vec<_>>();
+    Board {
+        title: format!("{} 学习积分排行", score.date),
+        subtitle,
+        max: rows.iter().map(|r| r.1).max().unwrap_or(0).max(1),
+        rows,
+    }
+}
+
+/// 把日报排行榜画成 PNG，群机器人的 markdown 显示不了表格
+pub struct Leaderboard {
+    font: FontVec,
+    top: usize,
+}
+
+impl Leaderboard {
+    /// 字体文件比较大，启动时读取一次，之后每天的日报共用
+    pub fn load(conf: &LeaderboardConfig) -> Result<Self> {
+        let data = std::fs::read(&conf.font_path)
+            .map_err(|e| anyhow!("读取字体文件 {} 失败: {}", conf.font_path, e))?;
+        Self::from_font(data, conf.top)
+            .map_err(|e| anyhow!("解析字体文件 {} 失败: {}", conf.font_path, e))
+    }
+
+    fn from_font(data: Vec<u8>, top: usize) -> Result<Self> {
+        let font = FontVec::try_from_vec(data).map_err(|e| anyhow!("{}", e))?;
+        Ok(Self { font, top })
+    }
+
+    pub fn render(&self, score: &MemberScore, org_id: u64) -> Result<Vec<u8>> {
+        let b = board(score, org_id, self.top);
+        let height = (HEADER + ROW * b.rows.len() as f32 + PADDING) as u32;
+        let mut pixmap =
+            Pixmap::new(WIDTH, height).ok_or(anyhow!("创建 {}x{} 的图片失败", WIDTH, height))?;
+        pixmap.fill(Color::WHITE);
+
+        let title_color = Color::from_rgba8(0x20, 0x20, 0x20, 0xff);
+        let gray = Color::from_rgba8(0x80, 0x80, 0x80, 0xff);
+        let red = Color::from_rgba8(0xd8, 0x3a, 0x2e, 0xff);
+        self.text(&mut pixmap, &b.title, PADDING, 24.0, 34.0, title_color);
+        self.text(&mut pixmap, &b.subtitle, PADDING, 74.0, 20.0, gray);
+
+        let bar_left = PADDING + 190.0;
+        let bar_width = WIDTH as f32 - bar_left - PADDING - 60.0;
+        for (i, (name, s)) in b.rows.iter().enumerate() {
+            let y = HEADER + ROW * i as f32;
+            if i % 2 == 0 {
+                fill_rect(
+                    &mut pixmap,
+                    0.0,
+                    y,
+                    WIDTH as f32,
+                    ROW,
+                    Color::from_rgba8(0xf5, 0xf5, 0xf5, 0xff),
+                );
+            }
+            let rank_color = if i < 3 { red } else { gray };
+            self.text(
+                &mut pixmap,
+                &(i + 1).to_string(),
+                PADDING,
+                y + 10.0,
+                22.0,
+                rank_color,
+            );
+            self.text(
+                &mut pixmap,
+                name,
+                PADDING + 44.0,
+                y + 10.0,
+                22.0,
+                title_color,
+            );
+            let w = bar_width * *s as f32 / b.max as f32;
+            fill_rect(
+                &mut pixmap,
+                bar_left,
+                y + 12.0,
+                w.max(2.0),
+                ROW - 24.0,
+                if *s > 0 { red } else { gray },
+            );
+            self.text(
+                &mut pixmap,
+                &s.to_string(),
+                bar_left + w.max(2.0) + 8.0,
+                y + 10.0,
+                22.0,
+                title_color,
+            );
+        }
+        pixmap
+            .encode_png()
+            .map_err(|e| anyhow!("生成排行榜图片失败: {}", e))
+    }
+
+    /// (x, top) 为文字左上角
+    fn text(&self, pixmap: &mut Pixmap, text: &str, x: f32, top: f32, size: f32, color: Color) {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        let baseline = top + scaled.ascent();
+        let mut caret = x;
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            let glyph = id.with_scale_and_position(size, point(caret, baseline));
+            caret += scaled.h_advance(id);
+            if let Some(g) = self.font.outline_glyph(glyph) {
+                let bounds = g.px_bounds();
+                g.draw(|gx, gy, coverage| {
+                    blend(
+                        pixmap,
+                        bounds.min.x as i32 + gx as i32,
+                        bounds.min.y as i32 + gy as i32,
+                        color,
+                        coverage,
+                    )
+                });
+            }
+        }
+    }
+}
+
+fn fill_rect(pixmap: &mut Pixmap, x: f32, y: f32, w: f32, h: f32, color: Color) {
+    let Some(rect) = Rect::from_xywh(x, y, w, h) else {
+        return;
+    };
+    let mut paint = Paint::default();
+    paint.set_color(color);
+    pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+}
+
+/// 背景都是不透明的，按覆盖率混合即可
+fn blend(pixmap: &mut Pixmap, x: i32, y: i32, color: Color, coverage: f32) {
+    let (w, h) = (pixmap.width() as i32, pixmap.height() as i32);
+    if x < 0 || y < 0 || x >= w || y >= h {
+        return;
+    }
+    let idx = (y * w + x) as usize;
+    let dst = pixmap.pixels()[idx];
+    let a = color.alpha() * coverage.clamp(0.0, 1.0);
+    let mix = |s: f32, d: u8| (s * a * 255.0 + d as f32 * (1.0 - a)).round() as u8;
+    if let Some(p) = PremultipliedColorU8::from_rgba(
+        mix(color.red(), dst.red()),
+        mix(color.green(), dst.green()),
+        mix(color.blue(), dst.blue()),
+        mix(1.0, dst.alpha()),
+    ) {
+        pixmap.pixels_mut()[idx] = p;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{member, OrganizationRank};
+
+    #[test]
+    fn test_board() {
+        let s = MemberScore {
+            date: "20231201".to_string(),
+            count: 4,
+            data: vec![
+                member("李四", 30),
+                member("赵六", 0),
+                member("张三", 40),
+                member("王五", 10),
+            ],
+            organization_rank: vec![OrganizationRank {
+                rank: 2,
+                org_name: "园区".to_string(),
+                org_id: 1,
+                stat_date: "20231201".to_string(),
+                avg_score: 30.5,
+                pre_diff_score: 1.5,
+            }],
+        };
+        assert_eq!(
+            board(&s, 1, 3),
+            Board {
+                title: "20231201 学习积分排行".to_string(),
+                subtitle: "园区 第 2 名  平均分 30.5  完成率 75.0%  3/4 人".to_string(),
+                rows: vec![
+                    ("张三".to_string(), 40),
+                    ("李四".to_string(), 30),
+                    ("王五".to_string(), 10)
+                ],
+                max: 40,
+            }
+        );
+        assert!(Leaderboard::load(&LeaderboardConfig {
+            font_path: "./not_exists.ttf".to_string(),
+            top: 10,
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        // 每个字符都是一个方块的测试字体
+        let l = Leaderboard::from_font(include_bytes!("testdata/square.ttf").to_vec(), 2)?;
+        let s = MemberScore {
+            date: "20231201".to_string(),
+            count: 3,
+            data: vec![member("张三", 40), member("李四", 0), member("王五", 10)],
+            organization_rank: vec![],
+        };
+        let png = l.render(&s, 1)?;
+        let pixmap = Pixmap::decode_png(&png).map_err(|e| anyhow!("{}", e))?;
+        assert_eq!(pixmap.width(), WIDTH);
+        assert_eq!(pixmap.height(), (HEADER + ROW * 2.0 + PADDING) as u32);
+        // 标题的第一个字画在左上角
+        let title = pixmap
+            .pixel(PADDING as u32 + 8, 24 + 20)
+            .ok_or(anyhow!("坐标超出图片范围"))?;
+        assert!(title.red() < 0x80);
+        // 右上角没有内容
+        let blank = pixmap
+            .pixel(WIDTH - 4, 4)
+            .ok_or(anyhow!("坐标超出图片范围"))?;
+        assert_eq!(blank.red(), 0xff);
+        Ok(())
+    }
+}
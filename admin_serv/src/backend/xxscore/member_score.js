@@ -0,0 +1,47 @@
+async function member_score(startDate, endDate, orgGrayId, withRank) {
+    console.log("I am running", startDate, endDate, orgGrayId);
+    const report = (apiCode, dataMap) => fetch("https://odrp.xuexi.cn/report/commonReport", {
+        headers: {
+            accept: "application/json, text/plain, */*", "content-type": "application/json;charset=UTF-8",
+        },
+        referrer: "https://study.xuexi.cn/",
+        referrerPolicy: "strict-origin-when-cross-origin",
+        body: JSON.stringify({apiCode: apiCode, dataMap: dataMap}),
+        method: "POST",
+        mode: "cors",
+        credentials: "include",
+    })
+        .then((resp) => resp.json())
+        .then((resp) => JSON.parse(resp.data_str));
+
+    // 每页最多 100 人，按 offset 翻页直到拿到 count 个人，接口返回空页时停止，
+    // 数据不完整的情况由调用方检查 count
+    const pageSize = 100;
+    let result = {
+        date: startDate === endDate ? startDate : startDate + "-" + endDate, count: 0, data: [],
+    };
+    for (let offset = 0; offset === 0 || result.data.length < result.count; offset += pageSize) {
+        const page = await report("ab4afc14", {
+            startDate: startDate,
+            endDate: endDate,
+            offset: offset,
+            sort: "totalScore",
+            pageSize: pageSize,
+            order: "desc",
+            isActivate: "",
+            orgGrayId: orgGrayId,
+        }).then((resp) => resp.dataList);
+        result.count = page.count;
+        if (!page.data || page.data.length === 0) {
+            break;
+        }
+        result.data.push(...page.data);
+    }
+    console.log("result is", result);
+
+    // 组织排名只有当前的，补录历史时不需要
+    result.organization_rank = withRank
+        ? await report("955eb740", {orgGrayId: orgGrayId}).then((resp) => resp.dataList.data)
+        : [];
+    return JSON.stringify(result);
+}
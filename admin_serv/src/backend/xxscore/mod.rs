@@ -1,133 +1,236 @@
 pub mod fetcher;
+mod leaderboard;
+pub mod period;
+mod report;
 mod xx;
-use crate::state::{Member, MemberScore};
-use anyhow::{anyhow, Result};
+use crate::backend::config::RemindMode;
+use crate::backend::contact::ContactMap;
+use crate::backend::export::{export, file_name};
+use crate::backend::pipeline::DailyReport;
+use crate::state::MemberScore;
+use anyhow::{Context, Result};
+pub use leaderboard::Leaderboard;
+pub use report::Reporter;
+use std::collections::HashSet;
 use std::ops::Sub;
-use tracing::{info, instrument};
-use wx::MsgApi;
+use std::time::Duration;
+use tracing::{info, instrument, warn};
+use wx::{MsgApi, WxError};
 pub use xx::XxAdmin;
 
-#[instrument(skip_all)]
-pub async fn daily_score<T: MsgApi>(
+/// 日报每一步的完成情况，重试时跳过已经发出去的，避免群里收到重复的消息
+pub trait Progress {
+    fn is_done(&self, step: &str) -> bool;
+    fn done(&mut self, step: &str);
+}
+
+impl Progress for HashSet<String> {
+    fn is_done(&self, step: &str) -> bool {
+        self.contains(step)
+    }
+
+    fn done(&mut self, step: &str) {
+        self.insert(step.to_string());
+    }
+}
+
+/// 按 daily 的配置发送一天的日报，progress 里已经完成的步骤不再发送
+#[instrument(skip_all, fields(date = %score.date))]
+pub async fn daily_score(
     mut score: MemberScore,
-    wechat_bots: Vec<String>,
-    org_id: u64,
-    admin_user: &str,
-    mp: &T,
+    daily: &DailyReport,
+    progress: &mut impl Progress,
 ) -> Result<()> {
+    let mp = &daily.mp;
+    let wechat_bots = &daily.wechat_bots;
+    let org_id = daily.org_id;
+    let admin_user = daily.admin_user.as_str();
+    let report = &daily.report;
     score.data.sort_by(|a, b| {
         b.range_real_score
             .partial_cmp(&a.range_real_score)
             .expect("分数比较失败")
     });
 
-    let inactive_count = score.data.iter().filter(|a| a.range_real_score < 1).count();
-    let mut grinds = score
-        .data
-        .iter()
-        .filter(|a| a.range_real_score > 34)
-        .map(|a| a.user_name.clone())
-        .collect::<Vec<String>>();
-    grinds.truncate(20);
-
-    // 解析组织的排名
-    let org_rank_msg = match score.organization_rank.iter().find(|a| a.org_id == org_id) {
-        Some(a) => {
-            if a.rank != 1 {
-                format!(
-                    r#"**园区排名 <font color="info">{}</font>名**, 平均分{}, <font color="comment">落后{}分</font>"#,
-                    a.rank, a.avg_score, a.pre_diff_score
-                )
-            } else {
-                r#"# 园区排名 <font color="info">第一</font>**"#.to_string()
-            }
-        }
-        None => "".to_string(),
-    };
-    let encourage_msg = if inactive_count > 0 {
-        format!("{}位同学未完成学习任务。", inactive_count)
-    } else {
-        "".to_string()
-    };
-
-    let grind_msg = if !grinds.is_empty() {
-        format!(
-            r#"**当日学霸**
-> {}"#,
-            grinds
-                .iter()
-                .map(|s| format!("<font color=\"info\">{}</font>", s))
-                .collect::<Vec<_>>()
-                .join("，")
-        )
-    } else {
-        "".to_string()
-    };
-    let msg = format!(
-        r#"**{} 学习积分情况**
-
-{}
-
-{}
-
-{}"#,
-        score.date.clone(),
-        grind_msg,
-        org_rank_msg,
-        encourage_msg
-    );
+    let reporter = Reporter::new(report)?;
+    let msg = reporter.daily(&score, org_id)?;
 
     for bot in wechat_bots {
-        mp.send_bot_msg(&msg, &bot)
+        let step = format!("bot/{}", bot);
+        if progress.is_done(&step) {
+            continue;
+        }
+        mp.send_bot_msg(&msg, bot)
             .await
-            .map_err(|e| anyhow!("发送消息给群机器人失败: {}", e))?;
+            .context("发送消息给群机器人失败")?;
+        progress.done(&step);
     }
-    // 发送全量汇总信息给管理员
-    total_notice(mp, &score.date, score.data, admin_user)
+    if let (Some(l), false) = (&daily.leaderboard, progress.is_done("leaderboard")) {
+        leaderboard_image(&score, org_id, l, wechat_bots, mp).await;
+        progress.done("leaderboard");
+    }
+    let title = format!("{} 学习积分情况", score.date);
+    for c in &daily.channels {
+        let step = format!("channel/{}", c.name());
+        if progress.is_done(&step) {
+            continue;
+        }
+        c.send_markdown(&title, &msg)
+            .await
+            .with_context(|| format!("发送消息给通知渠道 {} 失败", c.name()))?;
+        progress.done(&step);
+    }
+    // 各部门只收到自己部门的情况
+    if !report.dept_notice.is_empty() {
+        for (dept, msg) in reporter.depts_daily(&score)? {
+            for x in report.dept_notice.iter().filter(|x| x.dept_name == dept) {
+                for bot in &x.notice_bot {
+                    let step = format!("dept/{}/{}", dept, bot);
+                    if progress.is_done(&step) {
+                        continue;
+                    }
+                    mp.send_bot_msg(&msg, bot)
+                        .await
+                        .with_context(|| format!("发送 {} 部门消息给群机器人失败", dept))?;
+                    progress.done(&step);
+                }
+            }
+        }
+    }
+    if let (Some(url), false, false) = (
+        &report.study_url,
+        report.card_to.is_empty(),
+        progress.is_done("card"),
+    ) {
+        mp.send_template_card(
+            &report.card_to.join("|"),
+            reporter.daily_card(&score, org_id, url),
+        )
         .await
-        .map_err(|e| anyhow!("发送消息给管理员失败: {}", e))?;
+        .context("发送日报卡片失败")?;
+        progress.done("card");
+    }
+    if let (Some(mode), false) = (&report.remind_inactive, progress.is_done("remind")) {
+        remind_inactive(
+            &score,
+            mode,
+            &report.remind_text,
+            &daily.contacts,
+            wechat_bots,
+            mp,
+        )
+        .await;
+        progress.done("remind");
+    }
+    // 发送全量汇总信息给管理员
+    if !progress.is_done("admin") {
+        total_notice(mp, &reporter.admin(&score, org_id)?, admin_user)
+            .await
+            .context("发送消息给管理员失败")?;
+        progress.done("admin");
+    }
+    // 人多的时候 markdown 会被截断，完整的积分表用文件发送
+    if let (Some(format), false) = (report.attach, progress.is_done("attach")) {
+        let scores = [score];
+        let r = match export(&scores, format) {
+            Ok(data) => {
+                mp.send_file_msg(admin_user, &file_name(&scores, format), &data)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = r {
+            warn!("发送积分表格给管理员失败: {}", e);
+        }
+        progress.done("attach");
+    }
 
     Ok(())
 }
 
-async fn total_notice<T: MsgApi>(
+/// 图片是 markdown 日报的补充，失败只记录日志
+async fn leaderboard_image<T: MsgApi>(
+    score: &MemberScore,
+    org_id: u64,
+    leaderboard: &Leaderboard,
+    wechat_bots: &[String],
     mp: &T,
-    date: &str,
-    ms: Vec<Member>,
-    admin_user: &str,
-) -> Result<()> {
-    let msg = ms
+) {
+    let png = match leaderboard.render(score, org_id) {
+        Ok(png) => png,
+        Err(e) => {
+            warn!("生成排行榜图片失败: {}", e);
+            return;
+        }
+    };
+    for bot in wechat_bots {
+        if let Err(e) = mp.send_bot_image(&png, bot).await {
+            warn!("发送排行榜图片给群机器人失败: {}", e);
+        }
+    }
+}
+
+/// 提醒失败不影响日报，只记录日志
+async fn remind_inactive<T: MsgApi>(
+    score: &MemberScore,
+    mode: &RemindMode,
+    text: &str,
+    contacts: &ContactMap,
+    wechat_bots: &[String],
+    mp: &T,
+) {
+    let names = score
+        .data
         .iter()
-        .filter(|m| m.range_real_score > 0)
-        .map(|m| {
-            if m.range_real_score < 25 {
-                format!(
-                    "> {}: <font color=\"warning\">{}</font>",
-                    m.user_name, m.range_real_score
-                )
-            } else if m.range_real_score < 35 {
-                format!("> {}: {}", m.user_name, m.range_real_score)
-            } else {
-                format!(
-                    "> {}: <font color=\"info\">{}</font>",
-                    m.user_name, m.range_real_score
-                )
+        .filter(|m| m.range_real_score < 1)
+        .map(|m| m.user_name.as_str());
+    let (ids, missing) = contacts.resolve_all(names);
+    if !missing.is_empty() {
+        warn!(
+            "这些人没有对应的企业微信账号，无法提醒: {}",
+            missing.join(",")
+        );
+    }
+    if ids.is_empty() {
+        return;
+    }
+    if matches!(mode, RemindMode::Mention | RemindMode::Both) {
+        for bot in wechat_bots {
+            if let Err(e) = mp.send_bot_text_mention(text, &ids, bot).await {
+                warn!("在群里提醒未学习的人失败: {}", e);
             }
-        })
-        .collect::<Vec<_>>()
-        .join("\n");
-    info!("今日统计结果，{}", msg);
+        }
+    }
+    if matches!(mode, RemindMode::Private | RemindMode::Both) {
+        if let Err(e) = mp.send_text_msg(&ids.join("|"), text).await {
+            warn!("单独提醒未学习的人失败: {}", e);
+        }
+    }
+}
 
-    let inactive_count = ms.iter().filter(|m| m.range_real_score < 1).count();
-    mp.send_markdown_msg(
-        admin_user,
-        &format!(
-            "**{} 学习强国积分情况**\n{}\n\n{} 人未学习",
-            date, msg, inactive_count
-        ),
-    )
-    .await?;
-    Ok(())
+async fn total_notice<T: MsgApi>(mp: &T, msg: &str, admin_user: &str) -> Result<()> {
+    info!("今日统计结果，{}", msg);
+    let e = match mp.send_markdown_msg(admin_user, msg).await {
+        Ok(_) => return Ok(()),
+        Err(e) => e,
+    };
+    match WxError::of(&e) {
+        Some(WxError::UserNotFound(..)) => {
+            // 管理员配置错了不影响群里的日报
+            warn!(
+                "管理员 {} 不在企业微信通讯录里，跳过汇总通知: {}",
+                admin_user, e
+            );
+            Ok(())
+        }
+        Some(WxError::RateLimited(..)) => {
+            warn!("发送汇总通知被限流，一分钟后重试: {}", e);
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            mp.send_markdown_msg(admin_user, msg).await?;
+            Ok(())
+        }
+        _ => Err(e),
+    }
 }
 
 pub fn get_yesterday() -> String {
@@ -138,35 +241,129 @@ pub fn get_yesterday() -> String {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::backend::config::AdminConfig;
-    use crate::state::MemberScore;
-    use async_trait::async_trait;
-    use wx::MP;
+    use crate::backend::config::ReportConfig;
+    use crate::state::{Member, MemberScore};
+    use wx::mock::MockServer;
 
-    struct MockFetcher {
-        data: MemberScore,
+    /// 都在一部，用于测试部门日报
+    fn member(name: &str, score: u64) -> Member {
+        Member {
+            dept_names: "一部".to_string(),
+            ..crate::state::member(name, score)
+        }
+    }
+
+    fn daily(server: &MockServer) -> Result<DailyReport> {
+        Ok(DailyReport {
+            mp: server.mp(),
+            wechat_bots: vec![server.bot_url("org")],
+            org_id: 1,
+            admin_user: "admin".to_string(),
+            history: crate::backend::history::ScoreHistory::temporary()?,
+            report: ReportConfig::default(),
+            leaderboard: None,
+            channels: vec![],
+            contacts: ContactMap::default(),
+        })
     }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_cmd() -> Result<()> {
-        tracing_subscriber::fmt::init();
+        let server = MockServer::start().await?;
+        let report = ReportConfig {
+            dept_notice: vec![crate::backend::config::DeptNotice {
+                dept_name: "一部".to_string(),
+                notice_bot: vec![server.bot_url("dept")],
+            }],
+            study_url: Some("https://example.com/study".to_string()),
+            card_to: vec!["UserID1".to_string()],
+            remind_inactive: Some(RemindMode::Both),
+            attach: Some(crate::backend::export::ExportFormat::Csv),
+            ..Default::default()
+        };
 
-        let conf_str = include_str!("../../../config.toml");
-        let c = toml::from_str::<AdminConfig>(conf_str)?;
+        let daily = DailyReport {
+            report,
+            contacts: ContactMap::build(
+                &[wx::DirectoryUser {
+                    user_id: "lisi".to_string(),
+                    name: "李四".to_string(),
+                    department: vec![],
+                }],
+                Default::default(),
+            ),
+            ..daily(&server)?
+        };
+        let score = MemberScore {
+            date: "20231201".to_string(),
+            count: 2,
+            data: vec![member("李四", 0), member("张三", 40)],
+            organization_rank: vec![],
+        };
+        let mut progress = HashSet::new();
+        daily_score(score.clone(), &daily, &mut progress).await?;
 
-        // let j: MemberScore =
-        //     serde_json::from_str(&fs::read_to_string("./src/xxscore/test.json").await?)?;
+        let bot = server.requests_to("/cgi-bin/webhook/send");
+        assert_eq!(bot.len(), 3);
+        assert_eq!(bot[0].query["key"], "org");
+        let content = bot[0].json()?["markdown"]["content"].to_string();
+        assert!(content.contains("张三"));
+        assert!(content.contains("1位同学未完成学习任务"));
+        assert_eq!(bot[1].query["key"], "dept");
+        assert_eq!(bot[2].query["key"], "org");
+        assert_eq!(bot[2].json()?["text"]["mentioned_list"][0], "lisi");
 
-        // let xx_fetcher = MockFetcher { data: j };
-        let mp = MP::new(&c.corp_id, &c.corp_secret, c.agent_id);
+        let sent = server.requests_to("/cgi-bin/message/send");
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[0].json()?["touser"], "UserID1");
+        assert_eq!(sent[0].json()?["msgtype"], "template_card");
+        assert_eq!(sent[1].json()?["touser"], "lisi");
+        assert_eq!(sent[1].json()?["msgtype"], "text");
+        assert_eq!(sent[2].json()?["touser"], "admin");
+        assert_eq!(sent[2].json()?["msgtype"], "markdown");
+        assert_eq!(sent[3].json()?["touser"], "admin");
+        assert_eq!(sent[3].json()?["msgtype"], "file");
 
-        daily_score(
+        // 全部发完之后再发一次不会重复
+        daily_score(score, &daily, &mut progress).await?;
+        assert_eq!(server.requests_to("/cgi-bin/webhook/send").len(), 3);
+        assert_eq!(server.requests_to("/cgi-bin/message/send").len(), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_keep_wx_error() -> Result<()> {
+        let server = MockServer::start().await?;
+        server.inject_errcode("/cgi-bin/webhook/send", 45009, "api freq out of limit");
+        let e = daily_score(
             MemberScore::default(),
-            c.notice_bot.iter().map(|s| s.as_str()).collect(),
-            c.org_id,
-            c.admin_user.as_str(),
-            &mp,
+            &daily(&server)?,
+            &mut HashSet::new(),
         )
         .await
+        .unwrap_err();
+        // 加上说明之后仍然可以判断企业微信的错误类型
+        assert!(matches!(WxError::of(&e), Some(WxError::RateLimited(..))));
+        assert_eq!(e.to_string(), "发送消息给群机器人失败");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_total_notice_user_not_found() -> Result<()> {
+        let server = MockServer::start().await?;
+        server.inject_errcode(
+            "/cgi-bin/message/send",
+            81013,
+            "user & party & tag all invalid",
+        );
+        total_notice(&server.mp(), "汇总", "nobody").await?;
+
+        server.inject_errcode(
+            "/cgi-bin/message/send",
+            60020,
+            "not allow to access from your ip",
+        );
+        assert!(total_notice(&server.mp(), "汇总", "admin").await.is_err());
+        Ok(())
     }
 }
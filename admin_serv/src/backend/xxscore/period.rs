@@ -0,0 +1,362 @@
+use crate::backend::channel::Channel;
+use crate::backend::config::{ReportConfig, ReportSchedule};
+use crate::backend::history::ScoreHistory;
+use crate::state::MemberScore;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Sub;
+use std::sync::Arc;
+use tracing::{info, instrument};
+use wx::MsgApi;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Week,
+    Month,
+}
+
+impl Period {
+    /// today 之前最近一个完整的自然周(周一到周日)或自然月
+    pub fn range(&self, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            Period::Week => {
+                let start = today.sub(Duration::days(
+                    today.weekday().num_days_from_monday() as i64 + 7,
+                ));
+                (start, start + Duration::days(6))
+            }
+            Period::Month => {
+                let end = today.with_day(1).expect("每个月都有 1 号") - Duration::days(1);
+                (end.with_day(1).expect("每个月都有 1 号"), end)
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Period::Week => "周",
+            Period::Month => "月",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PeriodSummary {
+    pub start: String,
+    pub end: String,
+    pub days: usize,
+    pub avg_score: f64,
+    pub top: Vec<(String, u64)>,
+    pub zero_streaks: Vec<(String, usize)>,
+    pub dept_avg: Vec<(String, f64)>,
+}
+
+struct MemberStat {
+    name: String,
+    depts: Vec<String>,
+    total: u64,
+    zero_streak: usize,
+}
+
+/// 汇总一段时间的积分，scores 需要按日期升序
+pub fn summarize(scores: &[MemberScore], report: &ReportConfig) -> PeriodSummary {
+    let mut stats: HashMap<u64, MemberStat> = HashMap::new();
+    for s in scores {
+        for m in &s.data {
+            let st = stats.entry(m.user_id).or_insert_with(|| MemberStat {
+                name: m.user_name.clone(),
+                depts: split_dept_names(&m.dept_names),
+                total: 0,
+                zero_streak: 0,
+            });
+            st.total += m.range_real_score;
+            if m.range_real_score < 1 {
+                st.zero_streak += 1;
+            } else {
+                st.zero_streak = 0;
+            }
+        }
+    }
+    let days = scores.len();
+    let per_day = |total: u64| total as f64 / days.max(1) as f64;
+
+    let mut top = stats
+        .values()
+        .map(|s| (s.name.clone(), s.total))
+        .collect::<Vec<_>>();
+    top.sort_by_key(|t| std::cmp::Reverse(t.1));
+    top.truncate(report.period_top);
+
+    let mut zero_streaks = stats
+        .values()
+        .filter(|s| s.zero_streak >= report.zero_streak_days)
+        .map(|s| (s.name.clone(), s.zero_streak))
+        .collect::<Vec<_>>();
+    zero_streaks.sort_by_key(|z| std::cmp::Reverse(z.1));
+
+    let mut depts: HashMap<String, (u64, usize)> = HashMap::new();
+    for s in stats.values() {
+        for d in &s.depts {
+            let e = depts.entry(d.clone()).or_default();
+            e.0 += s.total;
+            e.1 += 1;
+        }
+    }
+    let mut dept_avg = depts
+        .into_iter()
+        .map(|(d, (total, n))| (d, per_day(total) / n as f64))
+        .collect::<Vec<_>>();
+    dept_avg.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let avg_score = if stats.is_empty() {
+        0.0
+    } else {
+        per_day(stats.values().map(|s| s.total).sum()) / stats.len() as f64
+    };
+
+    PeriodSummary {
+        start: scores.first().map(|s| s.date.clone()).unwrap_or_default(),
+        end: scores.last().map(|s| s.date.clone()).unwrap_or_default(),
+        days,
+        avg_score,
+        top,
+        zero_streaks,
+        dept_avg,
+    }
+}
+
+pub fn split_dept_names(s: &str) -> Vec<String> {
+    s.split([',', '，', '、'])
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn format_summary(
+    period: Period,
+    cur: &PeriodSummary,
+    prev: Option<&PeriodSummary>,
+    report: &ReportConfig,
+) -> String {
+    let mut lines = vec![format!(
+        "**{} - {} 学习积分{}报**",
+        cur.start,
+        cur.end,
+        period.name()
+    )];
+    let compare = match prev {
+        Some(p) if p.days > 0 => {
+            let diff = cur.avg_score - p.avg_score;
+            if diff >= 0.0 {
+                format!(
+                    r#"，比上{}<font color="info">提高{:.1}分</font>"#,
+                    period.name(),
+                    diff
+                )
+            } else {
+                format!(
+                    r#"，比上{}<font color="warning">下降{:.1}分</font>"#,
+                    period.name(),
+                    -diff
+                )
+            }
+        }
+        _ => "".to_string(),
+    };
+    lines.push(format!("人均日积分 {:.1}{}", cur.avg_score, compare));
+
+    if !cur.top.is_empty() {
+        lines.push(format!("\n**本{}学霸**", period.name()));
+        lines.extend(cur.top.iter().enumerate().map(|(i, (name, total))| {
+            format!(
+                "> {}. {}: <font color=\"info\">{}</font>",
+                i + 1,
+                name,
+                total
+            )
+        }));
+    }
+    if !cur.dept_avg.is_empty() {
+        lines.push("\n**部门人均日积分**".to_string());
+        lines.extend(
+            cur.dept_avg
+                .iter()
+                .map(|(d, avg)| format!("> {}: {:.1}", d, avg)),
+        );
+    }
+    if !cur.zero_streaks.is_empty() {
+        lines.push(format!("\n**连续{}天以上未学习**", report.zero_streak_days));
+        lines.push(format!(
+            "> {}",
+            cur.zero_streaks
+                .iter()
+                .map(|(name, n)| format!("<font color=\"warning\">{}({}天)</font>", name, n))
+                .collect::<Vec<_>>()
+                .join("，")
+        ));
+    }
+    lines.join("\n")
+}
+
+/// 按 schedule 里的周期汇总 today 之前的积分，发给 schedule 配置的群机器人和接收人
+#[instrument(skip(history, schedule, report, channels, mp), fields(period = ?schedule.period))]
+pub async fn period_score<T: MsgApi>(
+    history: &ScoreHistory,
+    schedule: &ReportSchedule,
+    today: NaiveDate,
+    report: &ReportConfig,
+    channels: &[Arc<dyn Channel>],
+    mp: &T,
+) -> Result<()> {
+    let period = schedule.period;
+    let load = |(start, end): (NaiveDate, NaiveDate)| {
+        history.range(
+            &start.format("%Y%m%d").to_string(),
+            &end.format("%Y%m%d").to_string(),
+        )
+    };
+    let cur_range = period.range(today);
+    let cur = load(cur_range)?;
+    if cur.is_empty() {
+        return Err(anyhow!(
+            "{} - {} 没有历史积分数据",
+            cur_range.0,
+            cur_range.1
+        ));
+    }
+    let prev = load(period.range(cur_range.0))?;
+    let cur = summarize(&cur, report);
+    let prev = summarize(&prev, report);
+    let msg = format_summary(period, &cur, Some(&prev), report);
+    info!("{}报: {}", period.name(), msg);
+
+    for bot in schedule.notice_bot.iter().flatten() {
+        mp.send_bot_msg(&msg, bot)
+            .await
+            .context("发送消息给群机器人失败")?;
+    }
+    if let Some(ids) = schedule.notice_id.as_ref().filter(|ids| !ids.is_empty()) {
+        mp.send_markdown_msg(&ids.join("|"), &msg)
+            .await
+            .context("发送消息给管理员失败")?;
+    }
+    let title = format!("学习积分{}报", period.name());
+    for c in channels {
+        c.send_markdown(&title, &msg)
+            .await
+            .with_context(|| format!("发送消息给通知渠道 {} 失败", c.name()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::Member;
+    use wx::mock::MockServer;
+    use wx::WxError;
+
+    fn member(user_id: u64, name: &str, dept: &str, score: u64) -> Member {
+        Member {
+            user_id,
+            dept_names: dept.to_string(),
+            ..crate::state::member(name, score)
+        }
+    }
+
+    fn day(date: &str, scores: &[u64]) -> MemberScore {
+        MemberScore {
+            date: date.to_string(),
+            count: scores.len() as i64,
+            data: vec![
+                member(1, "张三", "一部", scores[0]),
+                member(2, "李四", "一部,二部", scores[1]),
+                member(3, "王五", "二部", scores[2]),
+            ],
+            organization_rank: vec![],
+        }
+    }
+
+    #[test]
+    fn test_range() {
+        let d = |s: &str| NaiveDate::parse_from_str(s, "%Y%m%d").unwrap();
+        assert_eq!(
+            Period::Week.range(d("20231206")),
+            (d("20231127"), d("20231203"))
+        );
+        assert_eq!(
+            Period::Week.range(d("20231204")),
+            (d("20231127"), d("20231203"))
+        );
+        assert_eq!(
+            Period::Month.range(d("20240301")),
+            (d("20240201"), d("20240229"))
+        );
+        assert_eq!(
+            Period::Month.range(d("20240115")),
+            (d("20231201"), d("20231231"))
+        );
+    }
+
+    #[test]
+    fn test_summarize() {
+        let s = summarize(
+            &[
+                day("20231201", &[40, 0, 0]),
+                day("20231202", &[30, 0, 10]),
+                day("20231203", &[50, 0, 0]),
+            ],
+            &ReportConfig::default(),
+        );
+        assert_eq!(s.days, 3);
+        assert_eq!(s.top[0], ("张三".to_string(), 120));
+        assert_eq!(s.zero_streaks, vec![("李四".to_string(), 3)]);
+        assert_eq!(s.dept_avg[0].0, "一部");
+        assert!((s.dept_avg[0].1 - 20.0).abs() < 1e-6);
+        assert!((s.avg_score - 130.0 / 9.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_keep_wx_error() -> Result<()> {
+        let server = MockServer::start().await?;
+        let history = ScoreHistory::temporary()?;
+        history.save(&day("20231201", &[40, 0, 0]))?;
+        let d = |s: &str| NaiveDate::parse_from_str(s, "%Y%m%d").unwrap();
+        for (period, today) in [
+            (Period::Week, d("20231206")),
+            (Period::Month, d("20240102")),
+        ] {
+            let schedule = ReportSchedule {
+                name: None,
+                period,
+                day: 1,
+                hour: 9,
+                minute: 0,
+                cron: None,
+                workday_only: false,
+                notice_bot: Some(vec![server.bot_url("org")]),
+                notice_id: None,
+                channels: None,
+            };
+            server.inject_errcode("/cgi-bin/webhook/send", 45009, "api freq out of limit");
+            let e = period_score(
+                &history,
+                &schedule,
+                today,
+                &ReportConfig::default(),
+                &[],
+                &server.mp(),
+            )
+            .await
+            .unwrap_err();
+            // 加上说明之后仍然可以判断企业微信的错误类型
+            assert!(matches!(WxError::of(&e), Some(WxError::RateLimited(..))));
+            assert_eq!(e.to_string(), "发送消息给群机器人失败");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+use crate::backend::config::ReportConfig;
+use crate::backend::xxscore::period::split_dept_names;
+use crate::state::MemberScore;
+use anyhow::{anyhow, Result};
+use minijinja::{context, Environment};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use wx::TemplateCard;
+
+const DAILY_TEMPLATE: &str = include_str!("daily.md.j2");
+const ADMIN_TEMPLATE: &str = include_str!("admin.md.j2");
+const DEPT_TEMPLATE: &str = include_str!("dept.md.j2");
+
+#[derive(Serialize, Debug, Clone)]
+struct MemberView {
+    name: String,
+    score: u64,
+    color: String,
+    dept_names: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+struct DeptView {
+    name: String,
+    count: usize,
+    active: usize,
+    rate: f64, // 完成率，百分比
+    avg: f64,
+    members: Vec<MemberView>,
+    inactive: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct OrgRankView {
+    rank: u64,
+    org_name: String,
+    avg_score: f32,
+    pre_diff_score: f32,
+}
+
+/// 根据 `[report]` 配置渲染日报和管理员汇总
+pub struct Reporter {
+    conf: ReportConfig,
+    env: Environment<'static>,
+}
+
+impl Reporter {
+    pub fn new(conf: &ReportConfig) -> Result<Self> {
+        let mut env = Environment::new();
+        env.add_template_owned(
+            "daily.md",
+            conf.daily_template
+                .clone()
+                .unwrap_or_else(|| DAILY_TEMPLATE.to_string()),
+        )
+        .map_err(|e| anyhow!("日报模板有误: {}", e))?;
+        env.add_template_owned(
+            "admin.md",
+            conf.admin_template
+                .clone()
+                .unwrap_or_else(|| ADMIN_TEMPLATE.to_string()),
+        )
+        .map_err(|e| anyhow!("管理员汇总模板有误: {}", e))?;
+        env.add_template_owned(
+            "dept.md",
+            conf.dept_template
+                .clone()
+                .unwrap_or_else(|| DEPT_TEMPLATE.to_string()),
+        )
+        .map_err(|e| anyhow!("部门日报模板有误: {}", e))?;
+        Ok(Self {
+            conf: conf.clone(),
+            env,
+        })
+    }
+
+    fn member_view(&self, m: &crate::state::Member) -> MemberView {
+        MemberView {
+            name: m.user_name.clone(),
+            score: m.range_real_score,
+            color: self.conf.color_of(m.range_real_score).to_string(),
+            dept_names: m.dept_names.clone(),
+        }
+    }
+
+    /// 按 `Member.dept_names` 分组，同时属于多个部门的人会出现在每个部门里
+    fn depts(&self, score: &MemberScore) -> Vec<DeptView> {
+        let mut depts: BTreeMap<String, DeptView> = BTreeMap::new();
+        for m in &score.data {
+            for name in split_dept_names(&m.dept_names) {
+                let d = depts.entry(name.clone()).or_insert_with(|| DeptView {
+                    name,
+                    ..Default::default()
+                });
+                d.count += 1;
+                d.avg += m.range_real_score as f64;
+                if m.range_real_score > 0 {
+                    d.active += 1;
+                } else {
+                    d.inactive.push(m.user_name.clone());
+                }
+                d.members.push(self.member_view(m));
+            }
+        }
+        depts
+            .into_values()
+            .map(|mut d| {
+                d.rate = d.active as f64 * 100.0 / d.count as f64;
+                d.avg /= d.count as f64;
+                d
+            })
+            .collect()
+    }
+
+    /// score.data 需要已经按 range_real_score 降序
+    fn context(&self, score: &MemberScore, org_id: u64) -> minijinja::Value {
+        let inactive_count = score.data.iter().filter(|a| a.range_real_score < 1).count();
+        let mut grinds = score
+            .data
+            .iter()
+            .filter(|a| a.range_real_score > self.conf.grind_score)
+            .map(|a| a.user_name.clone())
+            .collect::<Vec<String>>();
+        grinds.truncate(self.conf.grind_limit);
+        let members = score
+            .data
+            .iter()
+            .map(|m| self.member_view(m))
+            .collect::<Vec<_>>();
+        let org_rank = score
+            .organization_rank
+            .iter()
+            .find(|a| a.org_id == org_id)
+            .map(|a| OrgRankView {
+                rank: a.rank,
+                org_name: a.org_name.clone(),
+                avg_score: a.avg_score,
+                pre_diff_score: a.pre_diff_score,
+            });
+
+        context! {
+            date => score.date,
+            count => score.count,
+            missing => score.missing(),
+            score => score,
+            members => members,
+            grinds => grinds,
+            inactive_count => inactive_count,
+            org_rank => org_rank,
+            depts => self.depts(score),
+        }
+    }
+
+    pub fn daily(&self, score: &MemberScore, org_id: u64) -> Result<String> {
+        self.render("daily.md", score, org_id)
+    }
+
+    pub fn admin(&self, score: &MemberScore, org_id: u64) -> Result<String> {
+        self.render("admin.md", score, org_id)
+    }
+
+    /// 模板卡片形式的日报，点击卡片或“去学习”跳转到 study_url
+    pub fn daily_card(&self, score: &MemberScore, org_id: u64, study_url: &str) -> TemplateCard {
+        let inactive_count = score.data.iter().filter(|a| a.range_real_score < 1).count();
+        let mut card =
+            TemplateCard::text_notice(&format!("{} 学习积分情况", score.date), study_url)
+                .source("学习强国")
+                .sub_title(&format!("{}位同学未完成学习任务", inactive_count));
+        if let Some(r) = score.organization_rank.iter().find(|a| a.org_id == org_id) {
+            card = card
+                .desc(&format!("{} 排名第 {} 名", r.org_name, r.rank))
+                .emphasis(&format!("{:.1}", r.avg_score), "平均分");
+        }
+        // 卡片最多展示 6 条
+        for m in score.data.iter().take(3) {
+            card = card.horizontal(&m.user_name, &m.range_real_score.to_string());
+        }
+        card.jump("去学习", study_url)
+    }
+
+    /// 每个部门一份日报，返回 (部门名称, 消息)
+    pub fn depts_daily(&self, score: &MemberScore) -> Result<Vec<(String, String)>> {
+        let tmpl = self.env.get_template("dept.md")?;
+        self.depts(score)
+            .into_iter()
+            .map(|d| {
+                let msg = tmpl
+                    .render(context! { date => score.date, dept => d })
+                    .map_err(|e| anyhow!("渲染 {} 部门日报失败: {}", d.name, e))?;
+                Ok((d.name, msg))
+            })
+            .collect()
+    }
+
+    fn render(&self, name: &str, score: &MemberScore, org_id: u64) -> Result<String> {
+        self.env
+            .get_template(name)?
+            .render(self.context(score, org_id))
+            .map_err(|e| anyhow!("渲染 {} 失败: {}", name, e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{member, OrganizationRank};
+
+    fn score() -> MemberScore {
+        MemberScore {
+            date: "20231201".to_string(),
+            count: 4,
+            data: vec![
+                member("张三", 40),
+                member("李四", 30),
+                member("王五", 10),
+                member("赵六", 0),
+            ],
+            organization_rank: vec![OrganizationRank {
+                rank: 2,
+                org_name: "园区".to_string(),
+                org_id: 1,
+                stat_date: "20231201".to_string(),
+                avg_score: 30.5,
+                pre_diff_score: 1.5,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_default_templates() -> Result<()> {
+        let r = Reporter::new(&ReportConfig::default())?;
+        assert_eq!(
+            r.daily(&score(), 1)?,
+            r#"**20231201 学习积分情况**
+
+**当日学霸**
+> <font color="info">张三</font>
+
+**园区排名 <font color="info">2</font>名**, 平均分30.5, <font color="comment">落后1.5分</font>
+
+1位同学未完成学习任务。"#
+        );
+        assert_eq!(
+            r.admin(&score(), 1)?,
+            r#"**20231201 学习强国积分情况**
+> 张三: <font color="info">40</font>
+> 李四: 30
+> 王五: <font color="warning">10</font>
+
+1 人未学习"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_depts() -> Result<()> {
+        let mut s = score();
+        s.data[0].dept_names = "一部".to_string();
+        s.data[1].dept_names = "一部,二部".to_string();
+        s.data[3].dept_names = "二部".to_string();
+        let r = Reporter::new(&ReportConfig::default())?;
+        let depts = r.depts_daily(&s)?;
+        assert_eq!(depts.len(), 2);
+        assert_eq!(depts[0].0, "一部");
+        assert_eq!(
+            depts[0].1,
+            r#"**20231201 一部 学习积分情况**
+完成率 <font color="info">100.0%</font>，平均分 35.0
+> 张三: <font color="info">40</font>
+> 李四: 30
+"#
+        );
+        assert_eq!(depts[1].0, "二部");
+        assert!(depts[1]
+            .1
+            .contains("完成率 <font color=\"info\">50.0%</font>，平均分 15.0"));
+        assert!(depts[1].1.ends_with("1 人未学习: 赵六"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_daily_card() -> Result<()> {
+        let r = Reporter::new(&ReportConfig::default())?;
+        let card = r.daily_card(&score(), 1, "https://example.com/study");
+        assert_eq!(card.card_type, "text_notice");
+        assert_eq!(card.main_title.unwrap().desc.unwrap(), "园区 排名第 2 名");
+        assert_eq!(card.horizontal_content_list.len(), 3);
+        assert_eq!(card.sub_title_text.unwrap(), "1位同学未完成学习任务");
+        assert_eq!(card.jump_list[0].title, "去学习");
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_template() -> Result<()> {
+        let r = Reporter::new(&ReportConfig {
+            grind_score: 20,
+            daily_template: Some("{{ grinds | join(',') }}".to_string()),
+            ..Default::default()
+        })?;
+        assert_eq!(r.daily(&score(), 1)?, "张三,李四");
+        Ok(())
+    }
+
+    #[test]
+    fn test_incomplete() -> Result<()> {
+        let r = Reporter::new(&ReportConfig::default())?;
+        let mut s = score();
+        s.count = 104;
+        assert!(r
+            .daily(&s, 1)?
+            .ends_with("<font color=\"warning\">数据不完整，还有 100 人的积分没有获取到</font>"));
+        assert!(r
+            .admin(&s, 1)?
+            .ends_with("<font color=\"warning\">数据不完整，还有 100 人的积分没有获取到</font>"));
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
-use crate::backend::xxscore::fetcher::browse_xx_admin;
+use crate::backend::pipeline::ReportPipeline;
+use crate::backend::xxscore::fetcher::{browse_xx_admin, AdminLogin};
 use crate::state::{State, StateChange};
 use anyhow::{anyhow, Result};
 use std::ops::{Add, Deref};
@@ -16,7 +17,12 @@ pub struct XxAdmin {
 }
 
 impl XxAdmin {
-    pub fn new(xx_org_gray_id: &str, proxy_server: Option<String>) -> Result<Self> {
+    pub fn new(
+        xx_org_gray_id: &str,
+        proxy_server: Option<String>,
+        login: AdminLogin,
+        pipeline: ReportPipeline,
+    ) -> Result<Self> {
         let cancel_token = CancellationToken::new();
         let (tx, rx) = std::sync::mpsc::channel::<StateChange>();
 
@@ -34,7 +40,7 @@ impl XxAdmin {
                         info!("admin 后台任务被取消");
                         return Err(anyhow!("进程退出，任务正常取消"))
                     }
-                    r = browse_xx_admin(tx.clone(), &cloned_xx_org_gray_id, &proxy_server) => {
+                    r = browse_xx_admin(tx.clone(), &cloned_xx_org_gray_id, &proxy_server, &login) => {
                         trace!("后台任务好像执行完了");
                         r
                     }
@@ -89,6 +95,8 @@ impl XxAdmin {
                     }
                     StateChange::Complete(ms) => {
                         info!("学习完成: {:?}", ms);
+                        // 不管有没有人在看网页，抓取完成就交给日报流水线
+                        pipeline.publish(ms.clone());
                         let mut s = state.write().unwrap();
                         *s = State::Complete(ms);
                         return;
@@ -140,7 +148,12 @@ mod test {
     async fn test_xx_admin() -> Result<()> {
         tracing_subscriber::fmt::init();
         info!("开始了");
-        let xa = XxAdmin::new("zW2GdDXrYrFXV3GOz5j6eg==", None)?;
+        let xa = XxAdmin::new(
+            "zW2GdDXrYrFXV3GOz5j6eg==",
+            None,
+            AdminLogin::new("default", None)?,
+            ReportPipeline::detached(),
+        )?;
         info!("start");
 
         loop {
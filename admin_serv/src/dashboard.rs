@@ -0,0 +1,213 @@
+use crate::state::{Dashboard, MemberTrend};
+use dioxus::prelude::*;
+use dioxus_fullstack::prelude::*;
+
+const CHART_WIDTH: f64 = 560.0;
+const CHART_HEIGHT: f64 = 120.0;
+const SPARK_WIDTH: f64 = 120.0;
+const SPARK_HEIGHT: f64 = 24.0;
+
+/// 折线的 points 属性，None 的点跳过；invert 时数值越小越靠上(用于排名)
+fn points(values: &[Option<f64>], width: f64, height: f64, invert: bool) -> String {
+    let known = values.iter().flatten();
+    let min = known.clone().copied().fold(f64::INFINITY, f64::min);
+    let max = known.copied().fold(f64::NEG_INFINITY, f64::max);
+    let span = if max > min { max - min } else { 1.0 };
+    let step = width / (values.len().max(2) - 1) as f64;
+    values
+        .iter()
+        .enumerate()
+        .filter_map(|(i, v)| {
+            let ratio = (v.as_ref()? - min) / span;
+            let y = if invert { ratio } else { 1.0 - ratio };
+            Some(format!("{:.1},{:.1}", i as f64 * step, y * height))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[component]
+fn Chart(
+    cx: Scope,
+    title: &'static str,
+    values: Vec<Option<f64>>,
+    latest: String,
+    invert: bool,
+) -> Element<'a> {
+    let pts = points(values, CHART_WIDTH, CHART_HEIGHT, *invert);
+    let view_box = format!("-4 -4 {} {}", CHART_WIDTH + 8.0, CHART_HEIGHT + 8.0);
+    cx.render(rsx! {
+        div {
+            h3 { "{title}：{latest}" }
+            svg {
+                width: "{CHART_WIDTH}",
+                height: "{CHART_HEIGHT}",
+                view_box: "{view_box}",
+                polyline {
+                    points: "{pts}",
+                    fill: "none",
+                    stroke: "#d83a2e",
+                    stroke_width: "2",
+                }
+            }
+        }
+    })
+}
+
+#[component]
+fn MemberRow(cx: Scope, member: MemberTrend) -> Element<'a> {
+    let latest = member
+        .latest()
+        .map(|s| s.to_string())
+        .unwrap_or("-".to_string());
+    let pts = points(
+        &member
+            .scores
+            .iter()
+            .map(|s| s.map(|s| s as f64))
+            .collect::<Vec<_>>(),
+        SPARK_WIDTH,
+        SPARK_HEIGHT,
+        false,
+    );
+    let view_box = format!("-2 -2 {} {}", SPARK_WIDTH + 4.0, SPARK_HEIGHT + 4.0);
+    cx.render(rsx! {
+        tr {
+            td { "{member.name}" }
+            td { "{member.dept_names}" }
+            td { "{latest}" }
+            td { "{member.total}" }
+            td {
+                svg {
+                    width: "{SPARK_WIDTH}",
+                    height: "{SPARK_HEIGHT}",
+                    view_box: "{view_box}",
+                    polyline {
+                        points: "{pts}",
+                        fill: "none",
+                        stroke: "#808080",
+                        stroke_width: "1.5",
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// 积分历史看板：组织日均分、完成率、排名走势和成员积分表
+#[component]
+pub fn DashboardView(cx: Scope, org: String) -> Element<'a> {
+    let days = use_state(cx, || 30i64);
+    let search = use_state(cx, String::new);
+    let data = use_future(cx, &(org.clone(), *days.get()), |(org, days)| async move {
+        get_dashboard(org, days).await
+    });
+
+    let body = match data.value() {
+        None => rsx! { p { "正在读取积分历史..." } },
+        Some(Err(e)) => rsx! { p { "读取积分历史失败：{e}" } },
+        Some(Ok(d)) if d.dates.is_empty() => rsx! { p { "还没有积分历史" } },
+        Some(Ok(d)) => {
+            let first = d.dates.first().cloned().unwrap_or_default();
+            let last = d.dates.last().cloned().unwrap_or_default();
+            let n = d.dates.len();
+            let stat = d.days.last().cloned().unwrap_or_default();
+            let keyword = search.get().trim().to_string();
+            let members = d
+                .members
+                .iter()
+                .filter(|m| {
+                    keyword.is_empty()
+                        || m.name.contains(&keyword)
+                        || m.dept_names.contains(&keyword)
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            rsx! {
+                p { "{first} 至 {last}，共 {n} 天" }
+                Chart {
+                    title: "日均分",
+                    values: d.days.iter().map(|x| Some(x.avg_score)).collect(),
+                    latest: format!("{:.1}", stat.avg_score),
+                    invert: false,
+                }
+                Chart {
+                    title: "完成率",
+                    values: d.days.iter().map(|x| Some(x.completion_rate)).collect(),
+                    latest: format!("{:.1}%", stat.completion_rate),
+                    invert: false,
+                }
+                Chart {
+                    title: "组织排名",
+                    values: d.days.iter().map(|x| x.rank.map(|r| r as f64)).collect(),
+                    latest: stat.rank.map(|r| format!("第 {} 名", r)).unwrap_or("-".to_string()),
+                    invert: true,
+                }
+                Chart {
+                    title: "组织平均分",
+                    values: d.days.iter().map(|x| x.org_avg_score.map(|s| s as f64)).collect(),
+                    latest: stat.org_avg_score.map(|s| s.to_string()).unwrap_or("-".to_string()),
+                    invert: false,
+                }
+                input {
+                    value: "{search}",
+                    placeholder: "搜索姓名或部门",
+                    oninput: move |e| search.set(e.value.clone()),
+                }
+                table {
+                    tr {
+                        th { "姓名" }
+                        th { "部门" }
+                        th { "最新" }
+                        th { "合计" }
+                        th { "走势" }
+                    }
+                    for m in members {
+                        MemberRow { key: "{m.user_id}", member: m }
+                    }
+                }
+            }
+        }
+    };
+
+    cx.render(rsx! {
+        h2 { "积分历史" }
+        select {
+            value: "{days}",
+            onchange: move |e| days.set(e.value.parse().unwrap_or(30)),
+            option { value: "7", "最近 7 天" }
+            option { value: "30", "最近 30 天" }
+            option { value: "90", "最近 90 天" }
+            option { value: "366", "最近一年" }
+        }
+        body
+    })
+}
+
+#[server]
+async fn get_dashboard(org: String, days: i64) -> Result<Dashboard, ServerFnError> {
+    match crate::backend::api::dashboard(&org, days).await {
+        Ok(s) => Ok(s),
+        Err(e) => Err(ServerFnError::ServerError(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_points() {
+        assert_eq!(
+            points(&[Some(0.0), Some(10.0), None, Some(5.0)], 30.0, 10.0, false),
+            "0.0,10.0 10.0,0.0 30.0,5.0"
+        );
+        // 排名越小越靠上
+        assert_eq!(
+            points(&[Some(3.0), Some(1.0)], 10.0, 10.0, true),
+            "0.0,10.0 10.0,0.0"
+        );
+        assert_eq!(points(&[Some(7.0)], 10.0, 10.0, false), "0.0,10.0");
+        assert_eq!(points(&[None, None], 10.0, 10.0, false), "");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::dashboard::DashboardView;
 use crate::qr::gen_qr_data_uri;
 use crate::state::State;
 use dioxus::prelude::*;
@@ -8,13 +9,19 @@ use futures_util::stream::StreamExt;
 
 pub fn app(cx: Scope) -> Element {
     let st = use_state(cx, || State::Prepare);
+    let org = use_state(cx, || "default".to_string());
+    let orgs = use_future(cx, (), |_| async move { get_orgs().await });
 
-    let tx = use_coroutine(cx, |mut rx: UnboundedReceiver<()>| {
+    let tx = use_coroutine(cx, |mut rx: UnboundedReceiver<String>| {
         to_owned![st];
         async move {
-            while let Some(_) = rx.next().await {
+            while let Some(mut org) = rx.next().await {
                 loop {
-                    let state = get_state().await;
+                    // 切换组织后查询新组织的状态
+                    while let Ok(Some(o)) = rx.try_next() {
+                        org = o;
+                    }
+                    let state = get_state(org.clone()).await;
                     match state {
                         Ok(s) => {
                             info!("state is {:?}", s);
@@ -33,7 +40,7 @@ pub fn app(cx: Scope) -> Element {
             }
         }
     });
-    tx.send(());
+    tx.send(org.get().clone());
     let ui = match st.get().clone() {
         State::Prepare => {
             rsx! { p { "正在准备" } }
@@ -59,8 +66,9 @@ pub fn app(cx: Scope) -> Element {
                 p { "稍后可以刷新页面再试试。" }
             }
         }
-        State::Complete(_) => {
-            rsx! { p { "学习强国分数统计完成" } }
+        State::Complete(s) => {
+            let (date, n) = (s.date, s.data.len());
+            rsx! { p { "学习强国分数统计完成：{date} 共 {n} 人" } }
         }
         State::Ready => {
             rsx! { p { "即将开始学习" } }
@@ -70,15 +78,47 @@ pub fn app(cx: Scope) -> Element {
         }
     };
 
+    let selector = match orgs.value() {
+        Some(Ok(names)) if names.len() > 1 => rsx! {
+            select {
+                value: "{org}",
+                onchange: move |e| {
+                    org.set(e.value.clone());
+                    st.set(State::Prepare);
+                },
+                for name in names.iter() {
+                    option { value: "{name}", "{name}" }
+                }
+            }
+        },
+        _ => rsx! { "" },
+    };
+
     cx.render(rsx! {
         h1 { "你好世界" }
+        selector
         ui
+        p {
+            "下载最近一次积分："
+            a { href: "/export?org={org}&format=xlsx", "Excel" }
+            " "
+            a { href: "/export?org={org}&format=csv", "CSV" }
+        }
+        DashboardView { org: org.get().clone() }
     })
 }
 
 #[server]
-async fn get_state() -> Result<State, ServerFnError> {
-    match crate::backend::api::try_get_state().await {
+async fn get_state(org: String) -> Result<State, ServerFnError> {
+    match crate::backend::api::try_get_state(&org).await {
+        Ok(s) => Ok(s),
+        Err(e) => Err(ServerFnError::ServerError(e.to_string())),
+    }
+}
+
+#[server]
+async fn get_orgs() -> Result<Vec<String>, ServerFnError> {
+    match crate::backend::api::org_names().await {
         Ok(s) => Ok(s),
         Err(e) => Err(ServerFnError::ServerError(e.to_string())),
     }
@@ -10,6 +10,7 @@
 
 #[cfg(feature = "ssr")]
 mod backend;
+mod dashboard;
 mod home;
 mod qr;
 pub mod state;
@@ -32,12 +33,17 @@ fn main() {
 #[cfg(any(not(feature = "web"), feature = "ssr"))]
 #[tokio::main]
 async fn main() {
+    use crate::backend::auth::{self, Auth};
+    use crate::backend::callback::AppCallback;
+    use crate::backend::channel::build_channels;
     use crate::backend::config::AdminConfig;
-    use crate::backend::StateSession;
+    use crate::backend::contact::ContactMap;
+    use crate::backend::history::ScoreHistory;
+    use crate::backend::org::{Org, Orgs};
+    use crate::backend::{AdminLogin, Leaderboard, Services, StateSession};
     use axum::routing::*;
     use axum::Extension;
     use clap::Parser;
-    use reqwest::Proxy;
 
     #[derive(Parser, Debug)]
     #[command(author, version, about, long_about = None)]
@@ -54,31 +60,79 @@ async fn main() {
         let contents = std::fs::read_to_string(&args.config).expect("读取配置文件失败");
         toml::from_str(contents.as_str()).expect("解析配置文件失败")
     };
-    let mp_ps = match p.mp.proxy_server {
-        Some(p) => Some(Proxy::all(p).expect("初始化代理错误")),
-        None => None,
-    };
-    let mp = wx::MP::new(&p.mp.corp_id, &p.mp.corp_secret, p.mp.agent_id, mp_ps);
-    let ss = StateSession::new(
-        mp.clone(),
-        &p.xx_org_gray_id,
-        p.proxy_server.clone(),
-        p.notice_bot.clone(),
-        p.org_id,
-        p.admin_user.clone(),
-    )
-    .expect("初始化 StateSession 失败");
+    p.validate().expect("配置文件有误");
+    let mp = p.mp.build().expect("初始化企业微信失败");
+    let channels =
+        build_channels(&p.report.channels, &p.channels, &mp).expect("初始化通知渠道失败");
+    let contacts = ContactMap::sync(&mp, &p.contact).await.unwrap_or_else(|e| {
+        tracing::warn!("同步企业微信通讯录失败: {}", e);
+        ContactMap::default()
+    });
+    let leaderboard = p
+        .report
+        .leaderboard
+        .as_ref()
+        .map(Leaderboard::load)
+        .transpose()
+        .expect("加载排行榜字体失败")
+        .map(std::sync::Arc::new);
+    let orgs = Orgs::new(
+        p.all_orgs()
+            .into_iter()
+            .map(|o| {
+                let history = ScoreHistory::open(o.history_path.as_deref().unwrap_or_default())
+                    .expect("打开历史数据库失败");
+                let services = Services {
+                    history: history.clone(),
+                    login: AdminLogin::new(&o.name, p.login.as_ref())
+                        .expect("初始化登录信息存储失败"),
+                    report: p.report.clone(),
+                    leaderboard: leaderboard.clone(),
+                    channels: channels.clone(),
+                    contacts: contacts.clone(),
+                };
+                let ss = StateSession::new(mp.clone(), p.proxy_server.clone(), &o, services)
+                    .expect("初始化 StateSession 失败");
+                Org {
+                    name: o.name,
+                    org_id: o.org_id,
+                    admin_user: o.admin_user,
+                    ss,
+                    history,
+                }
+            })
+            .collect(),
+    );
 
     let conf_path = args.config;
+    let o = orgs.clone();
+    let c = contacts.clone();
     tokio::spawn(async move {
-        _ = backend::serve(&conf_path).await;
+        _ = backend::serve(&conf_path, o, c).await;
     });
 
     // build our application with some routes
-    let app = Router::new()
+    // 登录页面和企业微信回调不需要登录，其他页面、导出和服务端函数都需要
+    let mut router = Router::new()
+        .route("/export", get(backend::api::export_scores))
         // Server side render the application, serve static assets, and register server functions
         .serve_dioxus_application("", ServeConfigBuilder::new(app, ()))
-        .layer(Extension(ss))
+        .layer(axum::middleware::from_fn(auth::login_layer))
+        .merge(auth::router());
+    if let Some(c) = &p.callback {
+        let crypt = wx::callback::MsgCrypt::new(&c.token, &c.encoding_aes_key, &p.mp.corp_id)
+            .expect("初始化企业微信回调失败");
+        router = router.nest(
+            "/wx/callback",
+            wx::callback::router(
+                crypt,
+                AppCallback::new(orgs.clone(), contacts.clone(), p.report.study_url.clone()),
+            ),
+        );
+    }
+    let app = router
+        .layer(Extension(Auth::new(p.auth.clone(), mp.clone())))
+        .layer(Extension(orgs))
         .layer(Extension(mp));
 
     // run it
@@ -31,6 +31,13 @@ pub struct MemberScore {
     pub data: Vec<Member>,
     pub organization_rank: Vec<OrganizationRank>,
 }
+
+impl MemberScore {
+    /// 后台返回的总人数比实际拿到的多出来的人数，大于 0 说明翻页没有拿全
+    pub fn missing(&self) -> usize {
+        (self.count.max(0) as usize).saturating_sub(self.data.len())
+    }
+}
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OrganizationRank {
     pub rank: u64,
@@ -67,3 +74,51 @@ pub enum State {
     Logged,
     Complete(MemberScore),
 }
+
+/// 管理后台看板数据，所有序列都和 dates 一一对应
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Dashboard {
+    pub dates: Vec<String>,
+    pub days: Vec<DayStat>,
+    pub members: Vec<MemberTrend>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DayStat {
+    pub avg_score: f64,
+    pub completion_rate: f64,
+    pub rank: Option<u64>,
+    pub org_avg_score: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MemberTrend {
+    pub user_id: u64,
+    pub name: String,
+    pub dept_names: String,
+    pub scores: Vec<Option<u64>>, // 当天不在名单里为 None
+    pub total: u64,
+}
+
+impl MemberTrend {
+    pub fn latest(&self) -> Option<u64> {
+        self.scores.last().copied().flatten()
+    }
+}
+
+/// 测试用的成员，其他字段用 `Member { user_id: 1, ..member(name, score) }` 覆盖
+#[cfg(test)]
+pub fn member(name: &str, score: u64) -> Member {
+    Member {
+        range_real_score: score,
+        dept_names: "".to_string(),
+        score_month: 0,
+        range_score: score,
+        dept_ids: "".to_string(),
+        user_name: name.to_string(),
+        user_id: 0,
+        total_score: 0,
+        org_id: 0,
+        is_activate: 1,
+    }
+}
@@ -0,0 +1,156 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use headless_chrome::protocol::cdp::Network::{Cookie, CookieParam};
+use rand::{thread_rng, RngCore};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use tracing::{debug, info, instrument};
+
+const NONCE_LEN: usize = 12;
+
+/// 按 uid 或 org 保存浏览器 cookie，文件用 AES-256-GCM 加密，
+/// 密钥由 secret 做 SHA-256 得到
+pub struct CookieStore {
+    dir: PathBuf,
+    cipher: Aes256Gcm,
+}
+
+impl CookieStore {
+    pub fn new(dir: impl Into<PathBuf>, secret: &str) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("创建 cookie 目录 {} 失败: {}", dir.display(), e))?;
+        Ok(Self {
+            dir,
+            cipher: Aes256Gcm::new(&Sha256::digest(secret.as_bytes())),
+        })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(anyhow!("cookie 的 key 只能包含字母、数字、_ 和 -: {}", key));
+        }
+        Ok(self.dir.join(format!("{}.cookie", key)))
+    }
+
+    #[instrument(skip(self, cookies))]
+    pub fn save(&self, key: &str, cookies: &[Cookie]) -> Result<()> {
+        let mut nonce = [0u8; NONCE_LEN];
+        thread_rng().fill_bytes(&mut nonce);
+        let data = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                serde_json::to_vec(cookies)?.as_ref(),
+            )
+            .map_err(|e| anyhow!("加密 cookie 失败: {}", e))?;
+        std::fs::write(self.path(key)?, [nonce.as_slice(), &data].concat())?;
+        info!("保存 cookie {} 个", cookies.len());
+        Ok(())
+    }
+
+    /// 读取还没有过期的 cookie，没有保存过或者全部过期时返回 None
+    #[instrument(skip(self))]
+    pub fn load(&self, key: &str) -> Result<Option<Vec<Cookie>>> {
+        let path = self.path(key)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read(path)?;
+        if data.len() < NONCE_LEN {
+            return Err(anyhow!("cookie 文件已损坏"));
+        }
+        let (nonce, data) = data.split_at(NONCE_LEN);
+        let plain = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), data)
+            .map_err(|_| anyhow!("解密 cookie 失败，密钥不对或者文件已损坏"))?;
+        let cookies: Vec<Cookie> = serde_json::from_slice(&plain)?;
+        let now = Local::now().timestamp() as f64;
+        let cookies = cookies
+            .into_iter()
+            .filter(|c| c.session || c.expires > now)
+            .collect::<Vec<_>>();
+        debug!("读取到有效 cookie {} 个", cookies.len());
+        Ok((!cookies.is_empty()).then_some(cookies))
+    }
+
+    pub fn remove(&self, key: &str) -> Result<()> {
+        let path = self.path(key)?;
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Network.getAllCookies 导出的 cookie 转成 Network.setCookies 的参数，
+/// 会话 cookie 不带过期时间
+pub fn to_params(cookies: &[Cookie]) -> Result<Vec<CookieParam>> {
+    cookies
+        .iter()
+        .map(|c| {
+            let mut v = serde_json::to_value(c)?;
+            if c.session {
+                if let Some(o) = v.as_object_mut() {
+                    o.remove("expires");
+                }
+            }
+            serde_json::from_value(v).map_err(|e| anyhow!("转换 cookie 失败: {}", e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cookie(name: &str, expires: f64, session: bool) -> Cookie {
+        serde_json::from_value(serde_json::json!({
+            "name": name, "value": "v", "domain": ".xuexi.cn", "path": "/",
+            "expires": expires, "size": 2, "httpOnly": true, "secure": true,
+            "session": session, "priority": "Medium", "sameParty": false,
+            "sourceScheme": "Secure", "sourcePort": 443,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cookie_store() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("cookie_test_{}", std::process::id()));
+        let store = CookieStore::new(&dir, "secret")?;
+        let future = Local::now().timestamp() as f64 + 3600.0;
+        store.save(
+            "12345",
+            &[
+                cookie("token", future, false),
+                cookie("sid", -1.0, true),
+                cookie("old", 1.0, false),
+            ],
+        )?;
+        let loaded = store.load("12345")?.unwrap();
+        assert_eq!(
+            loaded.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+            vec!["token", "sid"]
+        );
+        assert!(store.load("67890")?.is_none());
+        assert!(store.path("../etc").is_err());
+        // 密钥不对解不开
+        assert!(CookieStore::new(&dir, "other")?.load("12345").is_err());
+
+        let params = to_params(&loaded)?;
+        assert_eq!(params[0].expires, Some(future));
+        assert_eq!(params[1].expires, None);
+
+        store.remove("12345")?;
+        assert!(store.load("12345")?.is_none());
+        _ = std::fs::remove_dir_all(dir);
+        Ok(())
+    }
+}
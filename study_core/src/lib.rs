@@ -1,4 +1,6 @@
 #[cfg(feature = "server")]
+pub mod cookie;
+#[cfg(feature = "server")]
 mod core;
 #[cfg(feature = "server")]
 pub mod eval;
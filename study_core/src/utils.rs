@@ -1,9 +1,10 @@
+use crate::cookie::to_params;
 use crate::qrcode::decode_qr;
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use chrono::Local;
 use headless_chrome::browser::default_executable;
-use headless_chrome::protocol::cdp::Page;
+use headless_chrome::protocol::cdp::{Network, Page};
 use headless_chrome::{Browser, LaunchOptions, Tab};
 use rand::seq::SliceRandom;
 use rand::{thread_rng, Rng};
@@ -17,6 +18,29 @@ use tracing::{debug, info, instrument, trace};
 pub struct ChromeBrowser {
     browser: Browser,
     user_dir: PathBuf,
+    persistent: bool,
+}
+
+impl ChromeBrowser {
+    /// 通过 Network.getAllCookies 导出所有 cookie
+    pub fn export_cookies(&self) -> Result<Vec<Network::Cookie>> {
+        let tab = get_one_tab(self)?;
+        let r = tab
+            .call_method(Network::GetAllCookies(None))
+            .map_err(|e| anyhow!("导出 cookie 失败: {}", e))?;
+        Ok(r.cookies)
+    }
+
+    /// 通过 Network.setCookies 导入 cookie，之后打开的页面会带上这些 cookie
+    pub fn import_cookies(&self, cookies: &[Network::Cookie]) -> Result<()> {
+        let tab = get_one_tab(self)?;
+        tab.call_method(Network::SetCookies {
+            cookies: to_params(cookies)?,
+        })
+        .map_err(|e| anyhow!("导入 cookie 失败: {}", e))?;
+        debug!("导入 cookie {} 个", cookies.len());
+        Ok(())
+    }
 }
 
 impl Chrome for ChromeBrowser {
@@ -33,15 +57,35 @@ impl Chrome for ChromeBrowser {
 impl Drop for ChromeBrowser {
     fn drop(&mut self) {
         debug!("drop ChromeBrowser");
+        if self.persistent {
+            return;
+        }
         let temp_dir = self.user_dir.clone();
         _ = std::fs::remove_dir_all(temp_dir);
     }
 }
 
-#[instrument(skip_all)]
 pub fn new_browser(proxy_server: Option<String>) -> Result<ChromeBrowser> {
+    new_browser_with_profile(proxy_server, None)
+}
+
+/// profile_dir 为 None 时使用随机的临时目录，浏览器关闭后删除；
+/// 指定目录时登录状态保存在该目录里，下次启动可以继续使用
+#[instrument(skip_all)]
+pub fn new_browser_with_profile(
+    proxy_server: Option<String>,
+    profile_dir: Option<PathBuf>,
+) -> Result<ChromeBrowser> {
     trace!("准备启动浏览器");
-    let temp_dir = create_unique_temp_dir();
+    let persistent = profile_dir.is_some();
+    let temp_dir = match profile_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| anyhow!("创建浏览器目录 {} 失败: {}", dir.display(), e))?;
+            dir
+        }
+        None => create_unique_temp_dir(),
+    };
     let mut rng = thread_rng();
     let w = rng.gen_range(1440..2000);
     let h = rng.gen_range(720..1100);
@@ -64,6 +108,7 @@ pub fn new_browser(proxy_server: Option<String>) -> Result<ChromeBrowser> {
     Ok(ChromeBrowser {
         browser,
         user_dir: temp_dir,
+        persistent,
     })
 }
 
@@ -0,0 +1,510 @@
+//! 接收企业微信回调，包括 URL 验证和加密的消息/事件推送，
+//! 参考 https://developer.work.weixin.qq.com/document/path/90968
+//!
+//! ```ignore
+//! let crypt = MsgCrypt::new(token, encoding_aes_key, corp_id)?;
+//! let app = Router::new().nest("/wx/callback", wx::callback::router(crypt, handler));
+//! ```
+
+use aes::cipher::block_padding::NoPadding;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use base64::alphabet;
+use base64::engine::{GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use std::sync::Arc;
+use tracing::{debug, instrument, warn};
+
+type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+
+/// 企业微信用 32 字节块做 PKCS#7 填充
+const BLOCK_SIZE: usize = 32;
+
+/// EncodingAESKey 最后一个字符可能带有多余的位
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_allow_trailing_bits(true),
+);
+
+/// 企业微信回调的签名校验和加解密
+#[derive(Clone)]
+pub struct MsgCrypt {
+    token: String,
+    key: Vec<u8>,
+    receive_id: String,
+}
+
+impl MsgCrypt {
+    /// receive_id 为企业的 corp_id
+    pub fn new(token: &str, encoding_aes_key: &str, receive_id: &str) -> Result<Self> {
+        let key = BASE64
+            .decode(format!("{}=", encoding_aes_key))
+            .map_err(|e| anyhow!("EncodingAESKey 格式错误: {}", e))?;
+        if key.len() != 32 {
+            return Err(anyhow!("EncodingAESKey 长度错误，应为 43 个字符"));
+        }
+        Ok(Self {
+            token: token.to_string(),
+            key,
+            receive_id: receive_id.to_string(),
+        })
+    }
+
+    pub fn signature(&self, timestamp: &str, nonce: &str, encrypt: &str) -> String {
+        let mut parts = [self.token.as_str(), timestamp, nonce, encrypt];
+        parts.sort();
+        let mut h = Sha1::new();
+        h.update(parts.concat());
+        h.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn verify(
+        &self,
+        msg_signature: &str,
+        timestamp: &str,
+        nonce: &str,
+        encrypt: &str,
+    ) -> Result<()> {
+        if self.signature(timestamp, nonce, encrypt) != msg_signature {
+            return Err(anyhow!("回调签名校验失败"));
+        }
+        Ok(())
+    }
+
+    pub fn decrypt(&self, encrypt: &str) -> Result<String> {
+        let data = BASE64
+            .decode(encrypt)
+            .map_err(|e| anyhow!("回调密文格式错误: {}", e))?;
+        let mut plain = Aes256CbcDec::new_from_slices(&self.key, &self.key[..16])
+            .map_err(|e| anyhow!("初始化 AES 失败: {}", e))?
+            .decrypt_padded_vec_mut::<NoPadding>(&data)
+            .map_err(|e| anyhow!("解密回调失败: {}", e))?;
+
+        let pad = *plain.last().ok_or(anyhow!("回调明文为空"))? as usize;
+        if pad == 0 || pad > BLOCK_SIZE || pad > plain.len() {
+            return Err(anyhow!("回调明文填充错误"));
+        }
+        plain.truncate(plain.len() - pad);
+
+        // 16 字节随机串 + 4 字节网络序长度 + 消息 + receive_id
+        if plain.len() < 20 {
+            return Err(anyhow!("回调明文长度错误"));
+        }
+        let len = u32::from_be_bytes([plain[16], plain[17], plain[18], plain[19]]) as usize;
+        if plain.len() < 20 + len {
+            return Err(anyhow!("回调明文长度错误"));
+        }
+        let msg = String::from_utf8(plain[20..20 + len].to_vec())?;
+        let receive_id = String::from_utf8_lossy(&plain[20 + len..]);
+        if receive_id != self.receive_id {
+            return Err(anyhow!("回调 receive_id 不匹配: {}", receive_id));
+        }
+        Ok(msg)
+    }
+
+    pub fn encrypt(&self, msg: &str) -> Result<String> {
+        self.encrypt_with(msg, rand::random())
+    }
+
+    fn encrypt_with(&self, msg: &str, random: [u8; 16]) -> Result<String> {
+        let mut plain = random.to_vec();
+        plain.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+        plain.extend_from_slice(msg.as_bytes());
+        plain.extend_from_slice(self.receive_id.as_bytes());
+        let pad = BLOCK_SIZE - plain.len() % BLOCK_SIZE;
+        plain.extend(std::iter::repeat_n(pad as u8, pad));
+
+        let data = Aes256CbcEnc::new_from_slices(&self.key, &self.key[..16])
+            .map_err(|e| anyhow!("初始化 AES 失败: {}", e))?
+            .encrypt_padded_vec_mut::<NoPadding>(&plain);
+        Ok(BASE64.encode(data))
+    }
+
+    /// URL 验证，返回解密后的 echostr
+    pub fn verify_url(
+        &self,
+        msg_signature: &str,
+        timestamp: &str,
+        nonce: &str,
+        echostr: &str,
+    ) -> Result<String> {
+        self.verify(msg_signature, timestamp, nonce, echostr)?;
+        self.decrypt(echostr)
+    }
+
+    /// 校验签名并解密推送的 xml，返回明文 xml
+    pub fn decrypt_msg(
+        &self,
+        msg_signature: &str,
+        timestamp: &str,
+        nonce: &str,
+        body: &str,
+    ) -> Result<String> {
+        let b: EncryptedBody =
+            quick_xml::de::from_str(body).map_err(|e| anyhow!("解析回调 xml 失败: {}", e))?;
+        self.verify(msg_signature, timestamp, nonce, &b.encrypt)?;
+        self.decrypt(&b.encrypt)
+    }
+
+    /// 加密被动回复的 xml
+    pub fn encrypt_reply(&self, reply: &str, timestamp: &str, nonce: &str) -> Result<String> {
+        let encrypt = self.encrypt(reply)?;
+        let signature = self.signature(timestamp, nonce, &encrypt);
+        Ok(format!(
+            "<xml><Encrypt>{}</Encrypt><MsgSignature>{}</MsgSignature><TimeStamp>{}</TimeStamp><Nonce>{}</Nonce></xml>",
+            cdata(&encrypt),
+            cdata(&signature),
+            timestamp,
+            cdata(nonce)
+        ))
+    }
+}
+
+fn cdata(s: &str) -> String {
+    format!("<![CDATA[{}]]>", s.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+#[derive(Debug, Deserialize)]
+struct EncryptedBody {
+    #[serde(rename = "Encrypt")]
+    encrypt: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawMsg {
+    #[serde(rename = "ToUserName")]
+    to_user_name: String,
+    #[serde(rename = "FromUserName")]
+    from_user_name: String,
+    #[serde(rename = "CreateTime", default)]
+    create_time: i64,
+    #[serde(rename = "MsgType")]
+    msg_type: String,
+    #[serde(rename = "AgentID", default)]
+    agent_id: Option<i64>,
+    #[serde(rename = "Content", default)]
+    content: Option<String>,
+    #[serde(rename = "MsgId", default)]
+    msg_id: Option<String>,
+    #[serde(rename = "Event", default)]
+    event: Option<String>,
+    #[serde(rename = "EventKey", default)]
+    event_key: Option<String>,
+}
+
+/// 用户发给应用的消息或事件
+#[derive(Debug, Clone, PartialEq)]
+pub struct Incoming {
+    pub to_user_name: String,   // 企业微信 CorpID
+    pub from_user_name: String, // 发送者的 UserID
+    pub create_time: i64,
+    pub agent_id: Option<i64>,
+    pub msg: IncomingMsg,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IncomingMsg {
+    Text {
+        content: String,
+        msg_id: String,
+    },
+    Event(Event),
+    /// 暂不处理的消息类型，如 image、voice、location
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// 点击菜单
+    Click {
+        key: String,
+    },
+    /// 点击菜单跳转链接
+    View {
+        url: String,
+    },
+    Subscribe,
+    Unsubscribe,
+    EnterAgent,
+    /// 点击模板卡片按钮
+    TemplateCard {
+        key: String,
+    },
+    Other(String),
+}
+
+impl Incoming {
+    pub fn parse(xml: &str) -> Result<Self> {
+        let r: RawMsg =
+            quick_xml::de::from_str(xml).map_err(|e| anyhow!("解析回调消息失败: {}", e))?;
+        let key = r.event_key.unwrap_or_default();
+        let msg = match r.msg_type.as_str() {
+            "text" => IncomingMsg::Text {
+                content: r.content.unwrap_or_default(),
+                msg_id: r.msg_id.unwrap_or_default(),
+            },
+            "event" => IncomingMsg::Event(match r.event.unwrap_or_default().as_str() {
+                "click" => Event::Click { key },
+                "view" => Event::View { url: key },
+                "subscribe" => Event::Subscribe,
+                "unsubscribe" => Event::Unsubscribe,
+                "enter_agent" => Event::EnterAgent,
+                "template_card_event" => Event::TemplateCard { key },
+                other => Event::Other(other.to_string()),
+            }),
+            other => IncomingMsg::Other(other.to_string()),
+        };
+        Ok(Self {
+            to_user_name: r.to_user_name,
+            from_user_name: r.from_user_name,
+            create_time: r.create_time,
+            agent_id: r.agent_id,
+            msg,
+        })
+    }
+}
+
+/// 被动回复，需要在 5 秒内返回，耗时的操作应该改为主动发送消息
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Text(String),
+    Markdown(String),
+    Image { media_id: String },
+}
+
+impl Reply {
+    fn to_xml(&self, to_user: &str, from_user: &str, create_time: i64) -> String {
+        let body = match self {
+            Reply::Text(s) => format!(
+                "<MsgType>{}</MsgType><Content>{}</Content>",
+                cdata("text"),
+                cdata(s)
+            ),
+            Reply::Markdown(s) => format!(
+                "<MsgType>{}</MsgType><Markdown><Content>{}</Content></Markdown>",
+                cdata("markdown"),
+                cdata(s)
+            ),
+            Reply::Image { media_id } => format!(
+                "<MsgType>{}</MsgType><Image><MediaId>{}</MediaId></Image>",
+                cdata("image"),
+                cdata(media_id)
+            ),
+        };
+        format!(
+            "<xml><ToUserName>{}</ToUserName><FromUserName>{}</FromUserName><CreateTime>{}</CreateTime>{}</xml>",
+            cdata(to_user),
+            cdata(from_user),
+            create_time,
+            body
+        )
+    }
+}
+
+#[async_trait]
+pub trait CallbackHandler: Send + Sync + 'static {
+    /// 返回 None 时不回复
+    async fn handle(&self, msg: Incoming) -> Option<Reply>;
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackQuery {
+    msg_signature: String,
+    timestamp: String,
+    nonce: String,
+    echostr: Option<String>,
+}
+
+struct Inner<H> {
+    crypt: MsgCrypt,
+    handler: H,
+}
+
+/// GET 用于 URL 验证，POST 接收消息和事件
+pub fn router<H: CallbackHandler>(crypt: MsgCrypt, handler: H) -> Router {
+    Router::new()
+        .route("/", get(verify_url::<H>).post(receive::<H>))
+        .with_state(Arc::new(Inner { crypt, handler }))
+}
+
+#[instrument(skip_all)]
+async fn verify_url<H: CallbackHandler>(
+    State(s): State<Arc<Inner<H>>>,
+    Query(q): Query<CallbackQuery>,
+) -> Response {
+    let echostr = q.echostr.unwrap_or_default();
+    match s
+        .crypt
+        .verify_url(&q.msg_signature, &q.timestamp, &q.nonce, &echostr)
+    {
+        Ok(r) => r.into_response(),
+        Err(e) => {
+            warn!("企业微信回调 URL 验证失败: {}", e);
+            StatusCode::FORBIDDEN.into_response()
+        }
+    }
+}
+
+#[instrument(skip_all)]
+async fn receive<H: CallbackHandler>(
+    State(s): State<Arc<Inner<H>>>,
+    Query(q): Query<CallbackQuery>,
+    body: String,
+) -> Response {
+    let msg = match s
+        .crypt
+        .decrypt_msg(&q.msg_signature, &q.timestamp, &q.nonce, &body)
+        .and_then(|xml| Incoming::parse(&xml))
+    {
+        Ok(m) => m,
+        Err(e) => {
+            warn!("处理企业微信回调失败: {}", e);
+            return StatusCode::FORBIDDEN.into_response();
+        }
+    };
+    debug!("收到企业微信回调 {:?}", msg);
+    let (to_user, from_user, create_time) = (
+        msg.from_user_name.clone(),
+        msg.to_user_name.clone(),
+        msg.create_time,
+    );
+    let reply = match s.handler.handle(msg).await {
+        Some(r) => r,
+        None => return "".into_response(),
+    };
+    match s.crypt.encrypt_reply(
+        &reply.to_xml(&to_user, &from_user, create_time),
+        &q.timestamp,
+        &q.nonce,
+    ) {
+        Ok(r) => r.into_response(),
+        Err(e) => {
+            warn!("加密被动回复失败: {}", e);
+            "".into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    const AES_KEY: &str = "jWmYm7qr5nMoAUwZRjGtBxmz3KA1tkAj3ykkR6q2B2C";
+    const CORP_ID: &str = "wx5823bf96d3bd56c7";
+
+    fn crypt() -> MsgCrypt {
+        MsgCrypt::new("QDG6eK", AES_KEY, CORP_ID).unwrap()
+    }
+
+    #[test]
+    fn test_verify_url() -> Result<()> {
+        let echostr = "sKqRbbiSUnDhFHOvPjtUMWzf2R8PrpMiTTPaicBgqZlYW/WsF6SiFd2z9CriX2YpWYzF1yrNNQ0SomM3ovdnfA==";
+        assert_eq!(
+            crypt().verify_url(
+                "d9540010780b3a1763386bc609e9d23e2a51b856",
+                "1409659813",
+                "263014780",
+                echostr
+            )?,
+            "1616140317555161061"
+        );
+        assert!(crypt()
+            .verify_url("bad", "1409659813", "263014780", echostr)
+            .is_err());
+        assert_eq!(
+            crypt().encrypt_with("1616140317555161061", *b"0123456789abcdef")?,
+            echostr
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let m = Incoming::parse(
+            r#"<xml><ToUserName><![CDATA[ww1]]></ToUserName><FromUserName><![CDATA[zhangsan]]></FromUserName><CreateTime>1348831860</CreateTime><MsgType><![CDATA[text]]></MsgType><Content><![CDATA[我的分数]]></Content><MsgId>1234567890123456</MsgId><AgentID>1</AgentID></xml>"#,
+        )?;
+        assert_eq!(m.from_user_name, "zhangsan");
+        assert_eq!(m.agent_id, Some(1));
+        assert_eq!(
+            m.msg,
+            IncomingMsg::Text {
+                content: "我的分数".to_string(),
+                msg_id: "1234567890123456".to_string()
+            }
+        );
+
+        let m = Incoming::parse(
+            r#"<xml><ToUserName><![CDATA[ww1]]></ToUserName><FromUserName><![CDATA[zhangsan]]></FromUserName><CreateTime>1348831860</CreateTime><MsgType><![CDATA[event]]></MsgType><Event><![CDATA[click]]></Event><EventKey><![CDATA[start_study]]></EventKey><AgentID>1</AgentID></xml>"#,
+        )?;
+        assert_eq!(
+            m.msg,
+            IncomingMsg::Event(Event::Click {
+                key: "start_study".to_string()
+            })
+        );
+        Ok(())
+    }
+
+    struct Echo;
+
+    #[async_trait]
+    impl CallbackHandler for Echo {
+        async fn handle(&self, msg: Incoming) -> Option<Reply> {
+            match msg.msg {
+                IncomingMsg::Text { content, .. } => Some(Reply::Text(content)),
+                _ => None,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_router() -> Result<()> {
+        let c = crypt();
+        let app = router(c.clone(), Echo);
+
+        let inner = r#"<xml><ToUserName><![CDATA[ww1]]></ToUserName><FromUserName><![CDATA[zhangsan]]></FromUserName><CreateTime>1348831860</CreateTime><MsgType><![CDATA[text]]></MsgType><Content><![CDATA[开始学习]]></Content><MsgId>1</MsgId><AgentID>1</AgentID></xml>"#;
+        let encrypt = c.encrypt(inner)?;
+        let sig = c.signature("1409659813", "263014780", &encrypt);
+        let body = format!(
+            "<xml><ToUserName><![CDATA[ww1]]></ToUserName><AgentID><![CDATA[1]]></AgentID><Encrypt>{}</Encrypt></xml>",
+            cdata(&encrypt)
+        );
+        let resp = app
+            .clone()
+            .oneshot(
+                Request::post(format!(
+                    "/?msg_signature={}&timestamp=1409659813&nonce=263014780",
+                    sig
+                ))
+                .body(Body::from(body.clone()))?,
+            )
+            .await?;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bytes = hyper::body::to_bytes(resp.into_body()).await?;
+        let reply: EncryptedBody = quick_xml::de::from_str(std::str::from_utf8(&bytes)?)?;
+        let xml = c.decrypt(&reply.encrypt)?;
+        assert!(xml.contains("<ToUserName><![CDATA[zhangsan]]></ToUserName>"));
+        assert!(xml.contains("<Content><![CDATA[开始学习]]></Content>"));
+
+        let resp = app
+            .oneshot(
+                Request::post("/?msg_signature=bad&timestamp=1409659813&nonce=263014780")
+                    .body(Body::from(body))?,
+            )
+            .await?;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        Ok(())
+    }
+}
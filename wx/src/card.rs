@@ -0,0 +1,313 @@
+//! 模板卡片、任务卡片、图文和小程序通知消息的内容，
+//! 参考 https://developer.work.weixin.qq.com/document/path/90236
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CardTitle {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desc: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CardSource {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desc: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct HorizontalContent {
+    pub keyname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// type 1 跳转 url，2 跳转小程序
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct JumpItem {
+    #[serde(rename = "type")]
+    pub jump_type: i8,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CardAction {
+    #[serde(rename = "type")]
+    pub action_type: i8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CardButton {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<i8>,
+    pub key: String,
+}
+
+/// 模板卡片消息，text_notice 和 news_notice 必须带 card_action
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TemplateCard {
+    pub card_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<CardSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub main_title: Option<CardTitle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emphasis_content: Option<CardTitle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub_title_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub horizontal_content_list: Vec<HorizontalContent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jump_list: Vec<JumpItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub card_action: Option<CardAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub button_list: Vec<CardButton>,
+}
+
+impl TemplateCard {
+    /// 文本通知型卡片，点击卡片跳转 url
+    pub fn text_notice(title: &str, url: &str) -> Self {
+        Self {
+            card_type: "text_notice".to_string(),
+            main_title: Some(CardTitle {
+                title: Some(title.to_string()),
+                desc: None,
+            }),
+            card_action: Some(CardAction {
+                action_type: 1,
+                url: Some(url.to_string()),
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// 按钮交互型卡片，点击按钮后企业微信回调应用，task_id 在应用内唯一
+    pub fn button_interaction(title: &str, task_id: &str) -> Self {
+        Self {
+            card_type: "button_interaction".to_string(),
+            main_title: Some(CardTitle {
+                title: Some(title.to_string()),
+                desc: None,
+            }),
+            task_id: Some(task_id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn source(mut self, desc: &str) -> Self {
+        self.source = Some(CardSource {
+            icon_url: None,
+            desc: Some(desc.to_string()),
+        });
+        self
+    }
+
+    pub fn desc(mut self, desc: &str) -> Self {
+        self.main_title.get_or_insert_with(Default::default).desc = Some(desc.to_string());
+        self
+    }
+
+    /// 关键数据，title 大字显示
+    pub fn emphasis(mut self, title: &str, desc: &str) -> Self {
+        self.emphasis_content = Some(CardTitle {
+            title: Some(title.to_string()),
+            desc: Some(desc.to_string()),
+        });
+        self
+    }
+
+    pub fn sub_title(mut self, text: &str) -> Self {
+        self.sub_title_text = Some(text.to_string());
+        self
+    }
+
+    /// 二级标题 + 文本，最多 6 条
+    pub fn horizontal(mut self, key: &str, value: &str) -> Self {
+        self.horizontal_content_list.push(HorizontalContent {
+            keyname: key.to_string(),
+            value: Some(value.to_string()),
+        });
+        self
+    }
+
+    /// 跳转链接，最多 3 条
+    pub fn jump(mut self, title: &str, url: &str) -> Self {
+        self.jump_list.push(JumpItem {
+            jump_type: 1,
+            title: title.to_string(),
+            url: Some(url.to_string()),
+        });
+        self
+    }
+
+    pub fn button(mut self, text: &str, key: &str) -> Self {
+        self.button_list.push(CardButton {
+            text: text.to_string(),
+            style: None,
+            key: key.to_string(),
+        });
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TaskcardButton {
+    pub key: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replace_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>, // red 或 blue
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_bold: Option<bool>,
+}
+
+/// 任务卡片消息，点击按钮后企业微信回调应用
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct InteractiveTaskcard {
+    pub title: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub task_id: String,
+    pub btn: Vec<TaskcardButton>,
+}
+
+impl InteractiveTaskcard {
+    pub fn new(title: &str, description: &str, task_id: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            description: description.to_string(),
+            task_id: task_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+
+    /// replace_name 为点击后按钮显示的文字
+    pub fn button(mut self, key: &str, name: &str, replace_name: &str) -> Self {
+        self.btn.push(TaskcardButton {
+            key: key.to_string(),
+            name: name.to_string(),
+            replace_name: Some(replace_name.to_string()),
+            ..Default::default()
+        });
+        self
+    }
+}
+
+/// 图文消息，thumb_media_id 需要先上传图片素材
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MpArticle {
+    pub title: String,
+    pub thumb_media_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_source_url: Option<String>,
+    pub content: String, // 支持 html
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+}
+
+impl MpArticle {
+    pub fn new(title: &str, thumb_media_id: &str, content: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            thumb_media_id: thumb_media_id.to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    pub fn source_url(mut self, url: &str) -> Self {
+        self.content_source_url = Some(url.to_string());
+        self
+    }
+
+    pub fn digest(mut self, digest: &str) -> Self {
+        self.digest = Some(digest.to_string());
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MpnewsContent {
+    pub articles: Vec<MpArticle>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NoticeItem {
+    pub key: String,
+    pub value: String,
+}
+
+/// 小程序通知消息，只能发给关联了该小程序的应用
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MiniprogramNotice {
+    pub appid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<String>,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emphasis_first_item: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content_item: Vec<NoticeItem>,
+}
+
+impl MiniprogramNotice {
+    pub fn new(appid: &str, title: &str) -> Self {
+        Self {
+            appid: appid.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn page(mut self, page: &str) -> Self {
+        self.page = Some(page.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// 放大第一个 content_item
+    pub fn emphasis_first_item(mut self) -> Self {
+        self.emphasis_first_item = Some(true);
+        self
+    }
+
+    pub fn item(mut self, key: &str, value: &str) -> Self {
+        self.content_item.push(NoticeItem {
+            key: key.to_string(),
+            value: value.to_string(),
+        });
+        self
+    }
+}
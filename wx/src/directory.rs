@@ -0,0 +1,140 @@
+//! 通讯录，参考 https://developer.work.weixin.qq.com/document/path/90208
+//! 应用需要在“可见范围”里包含要读取的部门
+
+use crate::MP;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::{debug, instrument};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Department {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(rename = "parentid", default)]
+    pub parent_id: i64,
+    #[serde(default)]
+    pub order: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DirectoryUser {
+    #[serde(rename = "userid")]
+    pub user_id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub department: Vec<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DepartmentListResponse {
+    #[serde(default)]
+    department: Vec<Department>,
+}
+
+#[derive(Deserialize, Debug)]
+struct UserListResponse {
+    #[serde(rename = "userlist", default)]
+    user_list: Vec<DirectoryUser>,
+}
+
+#[async_trait::async_trait]
+pub trait DirectoryApi {
+    /// id 为空时获取应用可见范围内的全部部门
+    async fn department_list(&self, id: Option<i64>) -> Result<Vec<Department>>;
+    async fn user_list(&self, department_id: i64, fetch_child: bool) -> Result<Vec<DirectoryUser>>;
+    /// 应用可见范围内的所有成员，按 UserID 去重
+    async fn all_users(&self) -> Result<Vec<DirectoryUser>>;
+}
+
+#[async_trait::async_trait]
+impl DirectoryApi for MP {
+    #[instrument(skip(self))]
+    async fn department_list(&self, id: Option<i64>) -> Result<Vec<Department>> {
+        let query = match id {
+            Some(id) => vec![("id", id.to_string())],
+            None => vec![],
+        };
+        let r: DepartmentListResponse = self.get_json("/cgi-bin/department/list", &query).await?;
+        Ok(r.department)
+    }
+
+    #[instrument(skip(self))]
+    async fn user_list(&self, department_id: i64, fetch_child: bool) -> Result<Vec<DirectoryUser>> {
+        let r: UserListResponse = self
+            .get_json(
+                "/cgi-bin/user/simplelist",
+                &[
+                    ("department_id", department_id.to_string()),
+                    ("fetch_child", (fetch_child as i8).to_string()),
+                ],
+            )
+            .await?;
+        Ok(r.user_list)
+    }
+
+    #[instrument(skip(self))]
+    async fn all_users(&self) -> Result<Vec<DirectoryUser>> {
+        let depts = self.department_list(None).await?;
+        let ids = depts.iter().map(|d| d.id).collect::<HashSet<_>>();
+        // 只从最上层的部门递归获取，避免重复请求
+        let roots = depts.iter().filter(|d| !ids.contains(&d.parent_id));
+        let mut seen = HashSet::new();
+        let mut users = vec![];
+        for d in roots {
+            for u in self.user_list(d.id, true).await? {
+                if seen.insert(u.user_id.clone()) {
+                    users.push(u);
+                }
+            }
+        }
+        debug!("通讯录共 {} 个部门，{} 人", depts.len(), users.len());
+        Ok(users)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockServer;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_all_users() -> Result<()> {
+        let server = MockServer::start().await?;
+        server.set_response(
+            "/cgi-bin/department/list",
+            json!({
+                "errcode": 0,
+                "errmsg": "ok",
+                "department": [
+                    {"id": 2, "name": "园区", "parentid": 1, "order": 10},
+                    {"id": 3, "name": "一部", "parentid": 2, "order": 40}
+                ]
+            }),
+        );
+        server.set_response(
+            "/cgi-bin/user/simplelist",
+            json!({
+                "errcode": 0,
+                "errmsg": "ok",
+                "userlist": [
+                    {"userid": "zhangsan", "name": "张三", "department": [2, 3]},
+                    {"userid": "lisi", "name": "李四", "department": [3]}
+                ]
+            }),
+        );
+        let users = server.mp().all_users().await?;
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].user_id, "zhangsan");
+        assert_eq!(users[0].department, vec![2, 3]);
+
+        let req = server.requests_to("/cgi-bin/user/simplelist");
+        assert_eq!(req.len(), 1);
+        assert_eq!(req[0].query["department_id"], "2");
+        assert_eq!(req[0].query["fetch_child"], "1");
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+use std::fmt::Display;
+
+/// 企业微信接口返回的 errcode，参考 https://developer.work.weixin.qq.com/document/path/90313
+#[derive(Debug, Clone, PartialEq)]
+pub enum WxError {
+    /// 40014 不合法的 access_token，42001 access_token 已过期
+    InvalidToken(i64, String),
+    /// 45009 接口调用超过限制，45033 接口并发调用超过限制
+    RateLimited(i64, String),
+    /// 60111 UserID 不存在，81013 UserID、部门ID、标签ID全部非法或无权限，40031 不合法的 UserID 列表
+    UserNotFound(i64, String),
+    /// 40007 不合法的媒体文件 id，临时素材只有 3 天有效期
+    MediaExpired(i64, String),
+    Api(i64, String),
+}
+
+impl WxError {
+    /// errcode 为 0 时返回 None
+    pub fn from_code(errcode: i64, errmsg: &str) -> Option<Self> {
+        let errmsg = errmsg.to_string();
+        Some(match errcode {
+            0 => return None,
+            40014 | 42001 => WxError::InvalidToken(errcode, errmsg),
+            45009 | 45033 => WxError::RateLimited(errcode, errmsg),
+            60111 | 81013 | 40031 => WxError::UserNotFound(errcode, errmsg),
+            40007 => WxError::MediaExpired(errcode, errmsg),
+            _ => WxError::Api(errcode, errmsg),
+        })
+    }
+
+    pub fn errcode(&self) -> i64 {
+        match self {
+            WxError::InvalidToken(c, _)
+            | WxError::RateLimited(c, _)
+            | WxError::UserNotFound(c, _)
+            | WxError::MediaExpired(c, _)
+            | WxError::Api(c, _) => *c,
+        }
+    }
+
+    pub fn errmsg(&self) -> &str {
+        match self {
+            WxError::InvalidToken(_, m)
+            | WxError::RateLimited(_, m)
+            | WxError::UserNotFound(_, m)
+            | WxError::MediaExpired(_, m)
+            | WxError::Api(_, m) => m,
+        }
+    }
+
+    /// 从 anyhow::Error 里取出 WxError
+    pub fn of(e: &anyhow::Error) -> Option<&WxError> {
+        e.downcast_ref::<WxError>()
+    }
+}
+
+impl Display for WxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            WxError::InvalidToken(_, _) => "access_token 无效",
+            WxError::RateLimited(_, _) => "接口调用超过限制",
+            WxError::UserNotFound(_, _) => "接收人不存在",
+            WxError::MediaExpired(_, _) => "媒体文件无效或已过期",
+            WxError::Api(_, _) => "企业微信接口错误",
+        };
+        write!(
+            f,
+            "{}, errcode: {}, errmsg: {}",
+            kind,
+            self.errcode(),
+            self.errmsg()
+        )
+    }
+}
+
+impl std::error::Error for WxError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_code() {
+        assert_eq!(WxError::from_code(0, "ok"), None);
+        assert_eq!(
+            WxError::from_code(42001, "access_token expired"),
+            Some(WxError::InvalidToken(
+                42001,
+                "access_token expired".to_string()
+            ))
+        );
+        let e: anyhow::Error = WxError::from_code(81013, "invalid user").unwrap().into();
+        assert!(matches!(
+            WxError::of(&e),
+            Some(WxError::UserNotFound(81013, _))
+        ));
+        assert_eq!(
+            e.to_string(),
+            "接收人不存在, errcode: 81013, errmsg: invalid user"
+        );
+    }
+}
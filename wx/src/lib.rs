@@ -1,14 +1,30 @@
+#[cfg(any(test, feature = "callback"))]
+pub mod callback;
+mod card;
+mod directory;
+mod error;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 mod msg;
+mod oauth;
+mod outbox;
 
 use anyhow::{anyhow, Result};
+pub use card::*;
 use chrono::{Duration, Local};
+pub use directory::*;
+pub use error::WxError;
 pub use msg::*;
-use reqwest::{Client, ClientBuilder, Proxy};
-use serde::Deserialize;
+pub use oauth::*;
+pub use outbox::*;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, ClientBuilder, Proxy, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::ops::Add;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{instrument, trace};
+use tracing::{debug, instrument, trace, warn};
 
 struct Token {
     content: String,
@@ -16,11 +32,18 @@ struct Token {
 }
 #[derive(Debug, Deserialize, Clone)]
 struct AccessTokenResp {
-    errcode: isize,                   // `json:"errcode"`
-    errmsg: String,                   // `json:"errmsg"`
     pub access_token: Option<String>, // `json:"access_token" validate:"required"`
     pub expires_in: Option<i64>,      // `json:"expires_in" validate:"required"`
 }
+#[derive(Debug, Deserialize, Clone)]
+struct ErrResponse {
+    #[serde(default)]
+    errcode: i64,
+    #[serde(default)]
+    errmsg: String,
+}
+const DEFAULT_API_BASE: &str = "https://qyapi.weixin.qq.com";
+
 #[derive(Clone)]
 pub struct MP {
     corp_id: String,
@@ -28,6 +51,7 @@ pub struct MP {
     agent_id: i64,
     access_token: Arc<RwLock<Token>>,
     client: Client,
+    api_base: String,
 }
 
 impl MP {
@@ -52,23 +76,28 @@ impl MP {
                 expires_after: Local::now(),
             })),
             client,
+            api_base: DEFAULT_API_BASE.to_string(),
         }
     }
+
+    /// 替换企业微信 API 地址，用于私有化部署、代理或测试
+    pub fn with_api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.trim_end_matches('/').to_string();
+        self
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!("{}{}", self.api_base, path)
+    }
     #[instrument(skip(self))]
     async fn get_access_token(&self) -> Result<(String, i64)> {
-        let r = self
+        let resp = self
             .client
-            .get(format!(
-                "https://qyapi.weixin.qq.com/cgi-bin/gettoken?corpid={}&corpsecret={}",
-                self.corp_id, self.corp_secret
-            ))
+            .get(self.api("/cgi-bin/gettoken"))
+            .query(&[("corpid", &self.corp_id), ("corpsecret", &self.corp_secret)])
             .send()
-            .await?
-            .json::<AccessTokenResp>()
             .await?;
-        if r.errcode != 0 {
-            return Err(anyhow!("errcode: {}, errmsg: {}", r.errcode, r.errmsg));
-        }
+        let r = parse_response::<AccessTokenResp>(resp).await?;
         if let (Some(access_token), Some(expires_in)) = (r.access_token, r.expires_in) {
             return Ok((access_token, expires_in));
         }
@@ -94,4 +123,123 @@ impl MP {
         let r = self.access_token.read().await;
         Ok(r.content.clone())
     }
+
+    /// 带 access_token 调用接口，企业微信返回 token 无效或过期时刷新一次再重试
+    async fn call<R, F>(&self, build: F) -> Result<R>
+    where
+        R: DeserializeOwned,
+        F: Fn(&str) -> Result<RequestBuilder> + Send + Sync,
+    {
+        let mut retried = false;
+        loop {
+            let token = self.get_token().await?;
+            let resp = build(&token)?.send().await?;
+            match parse_response::<R>(resp).await {
+                Err(e)
+                    if !retried && matches!(WxError::of(&e), Some(WxError::InvalidToken(..))) =>
+                {
+                    warn!("access_token 失效，刷新后重试: {}", e);
+                    self.refresh_token().await?;
+                    retried = true;
+                }
+                r => return r,
+            }
+        }
+    }
+
+    async fn post_json<B, R>(&self, path: &str, body: &B) -> Result<R>
+    where
+        B: Serialize + ?Sized + Sync,
+        R: DeserializeOwned,
+    {
+        self.call(|token| {
+            Ok(self
+                .client
+                .post(self.api(path))
+                .query(&[("access_token", token)])
+                .json(body))
+        })
+        .await
+    }
+
+    async fn get_json<R>(&self, path: &str, query: &[(&str, String)]) -> Result<R>
+    where
+        R: DeserializeOwned,
+    {
+        self.call(|token| {
+            Ok(self
+                .client
+                .get(self.api(path))
+                .query(&[("access_token", token)])
+                .query(query))
+        })
+        .await
+    }
+
+    /// 上传图片素材，返回的 media_id 可以用作图文消息的 thumb_media_id，3 天内有效
+    pub async fn upload_image(&self, img: &[u8]) -> Result<String> {
+        self.upload_media("image", "thumb.png", "image/png", img)
+            .await
+    }
+
+    /// 上传临时素材，返回 media_id
+    #[instrument(skip(self, data))]
+    async fn upload_media(
+        &self,
+        media_type: &str,
+        file_name: &str,
+        mime: &str,
+        data: &[u8],
+    ) -> Result<String> {
+        let r: UploadMediaResponse = self
+            .call(|token| {
+                let part = Part::bytes(data.to_vec())
+                    .file_name(file_name.to_string())
+                    .mime_str(mime)?;
+                Ok(self
+                    .client
+                    .post(self.api("/cgi-bin/media/upload"))
+                    .query(&[("access_token", token), ("type", media_type)])
+                    .multipart(Form::new().part("media", part)))
+            })
+            .await?;
+        debug!("上传素材 {:?}", r);
+        Ok(r.media_id)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct UploadMediaResponse {
+    #[serde(default)]
+    media_id: String,
+    // #[serde(default)]
+    // created_at: String,
+    // #[serde(default, rename = "type")]
+    // media_type: String,
+}
+
+/// 先检查 errcode，非 0 时返回 WxError，否则解析为 R
+async fn parse_response<R: DeserializeOwned>(resp: Response) -> Result<R> {
+    let resp_status = resp.status();
+    let data_raw = resp.text().await?;
+    debug!("企业微信返回 [{}]{}", resp_status, data_raw);
+    let base = serde_json::from_str::<ErrResponse>(&data_raw).map_err(|e| {
+        anyhow!(
+            "解析企业微信返回失败, {:?}, text: [{}]{}",
+            e,
+            resp_status,
+            data_raw
+        )
+    })?;
+    if let Some(e) = WxError::from_code(base.errcode, &base.errmsg) {
+        return Err(e.into());
+    }
+    serde_json::from_str::<R>(&data_raw).map_err(|e| {
+        anyhow!(
+            "解析企业微信返回失败, {:?}, text: [{}]{}",
+            e,
+            resp_status,
+            data_raw
+        )
+    })
 }
@@ -0,0 +1,197 @@
+//! 进程内的企业微信假服务，实现 gettoken、media/upload、message/send、message/recall、
+//! webhook/send、通讯录和网页授权接口，记录收到的请求并可以注入 errcode，测试时不需要访问外网
+
+use crate::MP;
+use anyhow::Result;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tracing::{debug, error};
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn json(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    requests: Vec<RecordedRequest>,
+    errcodes: HashMap<String, VecDeque<(i64, String)>>,
+    responses: HashMap<String, serde_json::Value>,
+    seq: u64,
+}
+
+type Shared = Arc<Mutex<MockState>>;
+
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Shared,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockServer {
+    /// 在 127.0.0.1 的随机端口上启动，需要在 tokio runtime 里调用
+    pub async fn start() -> Result<Self> {
+        let state: Shared = Default::default();
+        let app = Router::new().fallback(handle).with_state(state.clone());
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = axum::Server::from_tcp(listener)?
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(async move {
+                _ = rx.await;
+            });
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                error!("mock 企业微信服务异常退出: {}", e);
+            }
+        });
+        debug!("mock 企业微信服务已启动: {}", addr);
+
+        Ok(Self {
+            addr,
+            state,
+            shutdown: Some(tx),
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// 群机器人地址
+    pub fn bot_url(&self, key: &str) -> String {
+        format!("{}/cgi-bin/webhook/send?key={}", self.url(), key)
+    }
+
+    /// 指向这个假服务的 MP
+    pub fn mp(&self) -> MP {
+        MP::new("mock_corp_id", "mock_corp_secret", 1000002, None).with_api_base(&self.url())
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn requests_to(&self, path: &str) -> Vec<RecordedRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.path == path)
+            .collect()
+    }
+
+    /// 下一次请求 path 时返回指定的 errcode，多次调用会依次生效
+    pub fn inject_errcode(&self, path: &str, errcode: i64, errmsg: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .errcodes
+            .entry(path.to_string())
+            .or_default()
+            .push_back((errcode, errmsg.to_string()));
+    }
+}
+
+impl MockServer {
+    /// 固定 path 的返回内容，用于通讯录等需要数据的接口
+    pub fn set_response(&self, path: &str, body: serde_json::Value) {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .insert(path.to_string(), body);
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            _ = tx.send(());
+        }
+    }
+}
+
+async fn handle(State(state): State<Shared>, uri: Uri, body: Bytes) -> Response {
+    let path = uri.path().to_string();
+    let query = form_urlencoded::parse(uri.query().unwrap_or("").as_bytes())
+        .into_owned()
+        .collect::<HashMap<String, String>>();
+    let mut s = state.lock().unwrap();
+    s.requests.push(RecordedRequest {
+        path: path.clone(),
+        query,
+        body: body.to_vec(),
+    });
+    s.seq += 1;
+    let seq = s.seq;
+
+    if let Some((errcode, errmsg)) = s.errcodes.get_mut(&path).and_then(|q| q.pop_front()) {
+        return Json(json!({ "errcode": errcode, "errmsg": errmsg })).into_response();
+    }
+
+    if let Some(r) = s.responses.get(&path) {
+        return Json(r.clone()).into_response();
+    }
+
+    let r = match path.as_str() {
+        "/cgi-bin/gettoken" => json!({
+            "errcode": 0,
+            "errmsg": "ok",
+            "access_token": format!("mock_token_{}", seq),
+            "expires_in": 7200,
+        }),
+        "/cgi-bin/media/upload" => json!({
+            "errcode": 0,
+            "errmsg": "ok",
+            "type": "image",
+            "media_id": format!("mock_media_{}", seq),
+            "created_at": "1380000000",
+        }),
+        "/cgi-bin/message/send" => json!({
+            "errcode": 0,
+            "errmsg": "ok",
+            "invaliduser": "",
+            "msgid": format!("mock_msg_{}", seq),
+        }),
+        "/cgi-bin/message/recall" | "/cgi-bin/webhook/send" => json!({
+            "errcode": 0,
+            "errmsg": "ok",
+        }),
+        "/cgi-bin/department/list" => json!({
+            "errcode": 0,
+            "errmsg": "ok",
+            "department": [],
+        }),
+        "/cgi-bin/user/simplelist" => json!({
+            "errcode": 0,
+            "errmsg": "ok",
+            "userlist": [],
+        }),
+        "/cgi-bin/auth/getuserinfo" => json!({
+            "errcode": 0,
+            "errmsg": "ok",
+            "userid": "mock_user",
+            "user_ticket": "",
+        }),
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    Json(r).into_response()
+}
@@ -7,16 +7,36 @@ use std::sync::mpsc::Sender;
 pub trait MsgApi {
     async fn recall_msgs(&self, msgs: Vec<String>) -> Result<()>;
     async fn send_image_msg(&self, to_user: &str, img_data: &[u8]) -> Result<String>;
+    async fn send_file_msg(&self, to_user: &str, file_name: &str, data: &[u8]) -> Result<String>;
     async fn send_text_msg(&self, to_user: &str, msg: &str) -> Result<String>;
     async fn send_markdown_msg(&self, to_user: &str, msg: &str) -> Result<String>;
     async fn send_bot_msg(&self, msg: &str, api: &str) -> Result<()>;
     async fn send_bot_text(&self, msg: &str, api: &str) -> Result<()>;
+    async fn send_bot_text_mention(
+        &self,
+        msg: &str,
+        mentioned_list: &[String],
+        api: &str,
+    ) -> Result<()>;
     async fn send_bot_image(&self, img: &[u8], api: &str) -> Result<()>;
+    async fn send_template_card(&self, to_user: &str, card: TemplateCard) -> Result<String>;
+    async fn send_interactive_taskcard(
+        &self,
+        to_user: &str,
+        card: InteractiveTaskcard,
+    ) -> Result<String>;
+    async fn send_mpnews(&self, to_user: &str, articles: Vec<MpArticle>) -> Result<String>;
+    async fn send_miniprogram_notice(
+        &self,
+        to_user: &str,
+        notice: MiniprogramNotice,
+    ) -> Result<String>;
     async fn send_msg(&self, d: SendMsgReq) -> Result<String>;
 }
 
-use crate::MP;
-use anyhow::anyhow;
+use crate::card::{InteractiveTaskcard, MiniprogramNotice, MpArticle, MpnewsContent, TemplateCard};
+use crate::{parse_response, ErrResponse, MP};
+use anyhow::{anyhow, Context};
 use base64::Engine;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -26,26 +46,11 @@ use tracing::{debug, error, instrument, trace};
 impl MsgApi for MP {
     #[instrument(skip(self))]
     async fn recall_msgs(&self, msgs: Vec<String>) -> Result<()> {
-        let token = self.get_token().await?;
-        let api = format!(
-            "https://qyapi.weixin.qq.com/cgi-bin/message/recall?access_token={}",
-            token
-        );
         for msg_id in msgs {
-            let res = self
-                .client
-                .post(&api)
-                .json(&json!({ "msgid": msg_id }))
-                .send()
-                .await?;
-            let resp_status = res.status();
-            if !resp_status.is_success() {
-                return Err(anyhow!(
-                    "撤回消息失败 error: [{}] {}",
-                    resp_status,
-                    res.text().await?
-                ));
-            }
+            let _: ErrResponse = self
+                .post_json("/cgi-bin/message/recall", &json!({ "msgid": msg_id }))
+                .await
+                .with_context(|| format!("撤回消息 {} 失败", msg_id))?;
         }
 
         Ok(())
@@ -53,34 +58,9 @@ impl MsgApi for MP {
 
     #[instrument(skip(self))]
     async fn send_image_msg(&self, to_user: &str, img_data: &[u8]) -> Result<String> {
-        let token = self.get_token().await?;
-        let api = format!(
-            "https://qyapi.weixin.qq.com/cgi-bin/media/upload?access_token={}",
-            token
-        );
-        let part = reqwest::multipart::Part::bytes(img_data.to_vec())
-            .file_name("qr.png")
-            .mime_str("image/png")?;
-        let resp = self
-            .client
-            .post(api)
-            .query(&[("type", "image")])
-            .multipart(reqwest::multipart::Form::new().part("media", part))
-            .send()
+        let media_id = self
+            .upload_media("image", "qr.png", "image/png", img_data)
             .await?;
-        let resp_status = resp.status();
-        let data_raw = resp.text().await?;
-
-        let data = serde_json::from_str::<UploadMediaResponse>(&data_raw).map_err(|e| {
-            anyhow!(
-                "send_image_msg failed, {:?}, text: [{}]{}",
-                e,
-                resp_status,
-                data_raw
-            )
-        })?;
-
-        debug!("上传图片， [{}]{:?}", resp_status, &data);
 
         self.send_msg(SendMsgReq::Image(SendImageMsgReq {
             common: SendMsgCommon {
@@ -90,13 +70,26 @@ impl MsgApi for MP {
                 ..Default::default()
             },
             image: MediaContent {
-                media_id: data.media_id,
+                media_id,
                 ..Default::default()
             },
         }))
         .await
     }
 
+    #[instrument(skip(self, data))]
+    async fn send_file_msg(&self, to_user: &str, file_name: &str, data: &[u8]) -> Result<String> {
+        let media_id = self
+            .upload_media("file", file_name, "application/octet-stream", data)
+            .await?;
+
+        self.send_msg(SendMsgReq::File(SendFileMsgReq {
+            common: SendMsgCommon::new(to_user, MsgType::File),
+            file: MediaContent::new(&media_id),
+        }))
+        .await
+    }
+
     #[instrument(skip(self))]
     async fn send_text_msg(&self, to_user: &str, msg: &str) -> Result<String> {
         self.send_msg(SendMsgReq::Text(SendTextMsgReq {
@@ -142,11 +135,7 @@ impl MsgApi for MP {
             }))
             .send()
             .await?;
-        debug!(
-            "企业微信机器人返回 bot resp: [{}]{:?}",
-            resp.status(),
-            resp.text().await?
-        );
+        let _: ErrResponse = parse_response(resp).await?;
         Ok(())
     }
     //curl 'https://qyapi.weixin.qq.com/cgi-bin/webhook/send?key='
@@ -165,11 +154,31 @@ impl MsgApi for MP {
             }))
             .send()
             .await?;
-        debug!(
-            "企业微信机器人返回 bot resp: [{}]{:?}",
-            resp.status(),
-            resp.text().await?
-        );
+        let _: ErrResponse = parse_response(resp).await?;
+        Ok(())
+    }
+
+    /// mentioned_list 为企业微信 UserID，"@all" 提醒所有人
+    #[instrument(skip(self))]
+    async fn send_bot_text_mention(
+        &self,
+        msg: &str,
+        mentioned_list: &[String],
+        api: &str,
+    ) -> Result<()> {
+        let resp = self
+            .client
+            .post(api)
+            .json(&json!({
+                "msgtype": "text",
+                "text": {
+                    "content": msg,
+                    "mentioned_list": mentioned_list
+                }
+            }))
+            .send()
+            .await?;
+        let _: ErrResponse = parse_response(resp).await?;
         Ok(())
     }
 
@@ -195,61 +204,80 @@ impl MsgApi for MP {
             }))
             .send()
             .await?;
-        debug!(
-            "企业微信机器人返回 bot resp: [{}]{:?}",
-            resp.status(),
-            resp.text().await?
-        );
+        let _: ErrResponse = parse_response(resp).await?;
         Ok(())
     }
 
     #[instrument(skip(self))]
-    async fn send_msg(&self, mut d: SendMsgReq) -> Result<String> {
-        let token = self.get_token().await?;
-        let api = format!(
-            "https://qyapi.weixin.qq.com/cgi-bin/message/send?access_token={}",
-            token
-        );
-        d.set_agent_id(self.agent_id);
+    async fn send_template_card(&self, to_user: &str, card: TemplateCard) -> Result<String> {
+        if card.card_action.is_none()
+            && (card.card_type == "text_notice" || card.card_type == "news_notice")
+        {
+            return Err(anyhow!(
+                "{} 类型的模板卡片必须设置 card_action",
+                card.card_type
+            ));
+        }
+        self.send_msg(SendMsgReq::TemplateCard(SendTemplateCardMsgReq {
+            common: SendMsgCommon::new(to_user, MsgType::TemplateCard),
+            template_card: card,
+        }))
+        .await
+    }
 
-        let resp = self.client.post(api).json(&d).send().await?;
+    #[instrument(skip(self))]
+    async fn send_interactive_taskcard(
+        &self,
+        to_user: &str,
+        card: InteractiveTaskcard,
+    ) -> Result<String> {
+        self.send_msg(SendMsgReq::InteractiveTaskcard(
+            SendInteractiveTaskcardMsgReq {
+                common: SendMsgCommon::new(to_user, MsgType::InteractiveTaskcard),
+                interactive_taskcard: card,
+            },
+        ))
+        .await
+    }
 
-        let resp_status = resp.status();
-        let data_raw = resp.text().await?;
+    #[instrument(skip(self))]
+    async fn send_mpnews(&self, to_user: &str, articles: Vec<MpArticle>) -> Result<String> {
+        self.send_msg(SendMsgReq::Mpnews(SendMpnewsMsgReq {
+            common: SendMsgCommon::new(to_user, MsgType::Mpnews),
+            mpnews: MpnewsContent { articles },
+        }))
+        .await
+    }
 
-        let data = serde_json::from_str::<BasicResponse>(&data_raw).map_err(|e| {
-            anyhow!(
-                "send_msg failed, {:?}, text: [{}]{}",
-                e,
-                resp_status,
-                data_raw
-            )
-        })?;
-        debug!("发送消息, [{}]{:?}", resp_status, data);
+    #[instrument(skip(self))]
+    async fn send_miniprogram_notice(
+        &self,
+        to_user: &str,
+        notice: MiniprogramNotice,
+    ) -> Result<String> {
+        self.send_msg(SendMsgReq::MiniprogramNotice(SendMiniprogramNoticeMsgReq {
+            common: SendMsgCommon::new(to_user, MsgType::MiniprogramNotice),
+            miniprogram_notice: notice,
+        }))
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn send_msg(&self, mut d: SendMsgReq) -> Result<String> {
+        d.set_agent_id(self.agent_id);
+        let data: BasicResponse = self.post_json("/cgi-bin/message/send", &d).await?;
+        debug!("发送消息, {:?}", data);
         Ok(data.msg_id)
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
-struct UploadMediaResponse {
-    // #[serde(rename = "errcode")]
-    // err_code: i64,
-    // #[serde(rename = "errmsg")]
-    // err_msg: String,
-    #[serde(default)]
-    media_id: String,
-    // #[serde(default)]
-    // created_at: String,
-    // #[serde(default, rename = "type")]
-    // media_type: String,
-}
 #[derive(Debug, Clone, Deserialize)]
 struct BasicResponse {
     // #[serde(rename = "errcode")]
     // err_code: i64,
     // #[serde(rename = "errmsg")]
     // err_msg: String,
-    #[serde(rename = "msgid")]
+    #[serde(rename = "msgid", default)]
     msg_id: String,
 }
 
@@ -264,10 +292,10 @@ enum MsgType {
     News,
     Mpnews,
     Markdown,
-    // MiniprogramNotice,
+    MiniprogramNotice,
     // Taskcard,
-    // InteractiveTaskcard,
-    // TemplateCard,
+    InteractiveTaskcard,
+    TemplateCard,
 }
 impl Default for MsgType {
     fn default() -> Self {
@@ -286,6 +314,9 @@ impl Display for MsgType {
             MsgType::TextCard => write!(f, "textcard"),
             MsgType::News => write!(f, "news"),
             MsgType::Mpnews => write!(f, "mpnews"),
+            MsgType::MiniprogramNotice => write!(f, "miniprogram_notice"),
+            MsgType::InteractiveTaskcard => write!(f, "interactive_taskcard"),
+            MsgType::TemplateCard => write!(f, "template_card"),
         }
     }
 }
@@ -302,6 +333,9 @@ impl From<String> for MsgType {
             "textcard" => MsgType::TextCard,
             "news" => MsgType::News,
             "mpnews" => MsgType::Mpnews,
+            "miniprogram_notice" => MsgType::MiniprogramNotice,
+            "interactive_taskcard" => MsgType::InteractiveTaskcard,
+            "template_card" => MsgType::TemplateCard,
             _ => MsgType::Text,
         }
     }
@@ -318,6 +352,9 @@ impl MsgType {
             MsgType::TextCard => "textcard",
             MsgType::News => "news",
             MsgType::Mpnews => "mpnews",
+            MsgType::MiniprogramNotice => "miniprogram_notice",
+            MsgType::InteractiveTaskcard => "interactive_taskcard",
+            MsgType::TemplateCard => "template_card",
         }
     }
 }
@@ -340,47 +377,92 @@ impl Serialize for MsgType {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-struct TextContent {
-    content: String,
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TextContent {
+    pub content: String,
 }
-#[derive(Serialize, Deserialize, Debug, Default)]
-struct MediaContent {
-    media_id: String,
+impl TextContent {
+    pub fn new(content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+        }
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MediaContent {
+    pub media_id: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    title: Option<String>,
+    pub title: Option<String>, // 仅视频消息
     #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
+    pub description: Option<String>, // 仅视频消息
 }
-#[derive(Serialize, Deserialize, Debug, Default)]
-struct TextCardContent {
-    title: String,
-    description: String,
-    url: String,
+impl MediaContent {
+    pub fn new(media_id: &str) -> Self {
+        Self {
+            media_id: media_id.to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TextCardContent {
+    pub title: String,
+    pub description: String, // 支持 <div class="gray|normal|highlight"> 标签
+    pub url: String,
     #[serde(rename = "btntxt")]
-    btn_txt: String,
+    pub btn_txt: String,
 }
-#[derive(Serialize, Deserialize, Debug, Default)]
-struct NewsContent {
-    articles: Vec<NewsArticle>,
+impl TextCardContent {
+    pub fn new(title: &str, description: &str, url: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            description: description.to_string(),
+            url: url.to_string(),
+            btn_txt: "详情".to_string(),
+        }
+    }
+    pub fn btn_txt(mut self, btn_txt: &str) -> Self {
+        self.btn_txt = btn_txt.to_string();
+        self
+    }
 }
-#[derive(Serialize, Deserialize, Debug, Default)]
-struct NewsArticle {
-    title: String,
-    description: String,
-    url: String,
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NewsContent {
+    pub articles: Vec<NewsArticle>,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NewsArticle {
+    pub title: String,
+    pub description: String,
+    pub url: String,
     #[serde(rename = "picurl")]
-    pic_url: String,
-}
-// #[derive(Serialize, Deserialize, Debug)]
-// struct MpnewsContent {
-//     articles: Vec<MpArticle>,
-// }
-// #[derive(Serialize, Deserialize, Debug)]
-// struct MpArticle {
-//     title: String,
-//     thumb_media_id: String,
-// }
+    pub pic_url: String,
+}
+impl NewsArticle {
+    pub fn new(title: &str, url: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = description.to_string();
+        self
+    }
+    pub fn pic_url(mut self, pic_url: &str) -> Self {
+        self.pic_url = pic_url.to_string();
+        self
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
@@ -393,7 +475,10 @@ pub enum SendMsgReq {
     Markdown(SendMarkdownMsgReq),
     TextCard(SendTextCardMsgReq),
     News(SendNewsMsgReq),
-    // Mpnews(SendMpnewsMsgReq),
+    Mpnews(SendMpnewsMsgReq),
+    TemplateCard(SendTemplateCardMsgReq),
+    InteractiveTaskcard(SendInteractiveTaskcardMsgReq),
+    MiniprogramNotice(SendMiniprogramNoticeMsgReq),
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
@@ -418,6 +503,16 @@ struct SendMsgCommon {
     duplicate_check_interval: Option<i32>,
 }
 
+impl SendMsgCommon {
+    fn new(to_user: &str, msg_type: MsgType) -> Self {
+        Self {
+            to_user: Some(to_user.to_string()),
+            msg_type,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SendImageMsgReq {
     #[serde(flatten)]
@@ -467,12 +562,30 @@ pub struct SendNewsMsgReq {
     common: SendMsgCommon,
     news: NewsContent,
 }
-// #[derive(Serialize, Deserialize, Debug)]
-// pub struct SendMpnewsMsgReq {
-//     #[serde(flatten)]
-//     common: SendMsgCommon,
-//     mpnews: ,
-// }
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SendMpnewsMsgReq {
+    #[serde(flatten)]
+    common: SendMsgCommon,
+    mpnews: MpnewsContent,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SendTemplateCardMsgReq {
+    #[serde(flatten)]
+    common: SendMsgCommon,
+    template_card: TemplateCard,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SendInteractiveTaskcardMsgReq {
+    #[serde(flatten)]
+    common: SendMsgCommon,
+    interactive_taskcard: InteractiveTaskcard,
+}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SendMiniprogramNoticeMsgReq {
+    #[serde(flatten)]
+    common: SendMsgCommon,
+    miniprogram_notice: MiniprogramNotice,
+}
 
 impl SendMsgReq {
     fn set_agent_id(&mut self, agent_id: i64) {
@@ -485,10 +598,146 @@ impl SendMsgReq {
             SendMsgReq::Markdown(d) => d.common.agent_id = agent_id,
             SendMsgReq::TextCard(d) => d.common.agent_id = agent_id,
             SendMsgReq::News(d) => d.common.agent_id = agent_id,
+            SendMsgReq::Mpnews(d) => d.common.agent_id = agent_id,
+            SendMsgReq::TemplateCard(d) => d.common.agent_id = agent_id,
+            SendMsgReq::InteractiveTaskcard(d) => d.common.agent_id = agent_id,
+            SendMsgReq::MiniprogramNotice(d) => d.common.agent_id = agent_id,
         }
     }
 }
 
+/// 应用消息的消息体
+#[derive(Debug, Clone)]
+pub enum MsgBody {
+    Text(TextContent),
+    Image(MediaContent),
+    Voice(MediaContent),
+    Video(MediaContent),
+    File(MediaContent),
+    Markdown(TextContent),
+    TextCard(TextCardContent),
+    News(NewsContent),
+    Mpnews(MpnewsContent),
+    TemplateCard(TemplateCard),
+    InteractiveTaskcard(InteractiveTaskcard),
+    MiniprogramNotice(MiniprogramNotice),
+}
+
+impl SendMsgReq {
+    /// ```ignore
+    /// let req = SendMsgReq::builder()
+    ///     .to_party("2")
+    ///     .duplicate_check(1800)
+    ///     .build(MsgBody::TextCard(TextCardContent::new("学习提醒", "今天还没学习", url)))?;
+    /// mp.send_msg(req).await?;
+    /// ```
+    pub fn builder() -> SendMsgBuilder {
+        SendMsgBuilder::default()
+    }
+}
+
+/// 多个接收人用 `|` 分隔，agentid 在发送时由 MP 填写
+#[derive(Debug, Default)]
+pub struct SendMsgBuilder {
+    common: SendMsgCommon,
+}
+
+impl SendMsgBuilder {
+    pub fn to_user(mut self, to_user: &str) -> Self {
+        self.common.to_user = Some(to_user.to_string());
+        self
+    }
+    pub fn to_party(mut self, to_party: &str) -> Self {
+        self.common.to_party = Some(to_party.to_string());
+        self
+    }
+    pub fn to_tag(mut self, to_tag: &str) -> Self {
+        self.common.to_tag = Some(to_tag.to_string());
+        self
+    }
+    /// 保密消息，不能分享且带水印
+    pub fn safe(mut self, safe: bool) -> Self {
+        self.common.safe = Some(safe as i8);
+        self
+    }
+    pub fn enable_id_trans(mut self, enable: bool) -> Self {
+        self.common.enable_id_trans = Some(enable as i8);
+        self
+    }
+    /// 开启重复消息检查，interval 秒内相同内容的消息不会重复发送，最大 4 小时
+    pub fn duplicate_check(mut self, interval: i32) -> Self {
+        self.common.enable_duplicate_check = Some(1);
+        self.common.duplicate_check_interval = Some(interval);
+        self
+    }
+
+    pub fn build(self, body: MsgBody) -> Result<SendMsgReq> {
+        let mut common = self.common;
+        if common.to_user.is_none() && common.to_party.is_none() && common.to_tag.is_none() {
+            return Err(anyhow!("touser、toparty、totag 不能同时为空"));
+        }
+        Ok(match body {
+            MsgBody::Text(text) => {
+                common.msg_type = MsgType::Text;
+                SendMsgReq::Text(SendTextMsgReq { common, text })
+            }
+            MsgBody::Image(image) => {
+                common.msg_type = MsgType::Image;
+                SendMsgReq::Image(SendImageMsgReq { common, image })
+            }
+            MsgBody::Voice(voice) => {
+                common.msg_type = MsgType::Voice;
+                SendMsgReq::Voice(SendVoiceMsgReq { common, voice })
+            }
+            MsgBody::Video(video) => {
+                common.msg_type = MsgType::Video;
+                SendMsgReq::Video(SendVideoMsgReq { common, video })
+            }
+            MsgBody::File(file) => {
+                common.msg_type = MsgType::File;
+                SendMsgReq::File(SendFileMsgReq { common, file })
+            }
+            MsgBody::Markdown(markdown) => {
+                common.msg_type = MsgType::Markdown;
+                SendMsgReq::Markdown(SendMarkdownMsgReq { common, markdown })
+            }
+            MsgBody::TextCard(textcard) => {
+                common.msg_type = MsgType::TextCard;
+                SendMsgReq::TextCard(SendTextCardMsgReq { common, textcard })
+            }
+            MsgBody::News(news) => {
+                common.msg_type = MsgType::News;
+                SendMsgReq::News(SendNewsMsgReq { common, news })
+            }
+            MsgBody::Mpnews(mpnews) => {
+                common.msg_type = MsgType::Mpnews;
+                SendMsgReq::Mpnews(SendMpnewsMsgReq { common, mpnews })
+            }
+            MsgBody::TemplateCard(template_card) => {
+                common.msg_type = MsgType::TemplateCard;
+                SendMsgReq::TemplateCard(SendTemplateCardMsgReq {
+                    common,
+                    template_card,
+                })
+            }
+            MsgBody::InteractiveTaskcard(interactive_taskcard) => {
+                common.msg_type = MsgType::InteractiveTaskcard;
+                SendMsgReq::InteractiveTaskcard(SendInteractiveTaskcardMsgReq {
+                    common,
+                    interactive_taskcard,
+                })
+            }
+            MsgBody::MiniprogramNotice(miniprogram_notice) => {
+                common.msg_type = MsgType::MiniprogramNotice;
+                SendMsgReq::MiniprogramNotice(SendMiniprogramNoticeMsgReq {
+                    common,
+                    miniprogram_notice,
+                })
+            }
+        })
+    }
+}
+
 pub struct DropMsg {
     tx: Sender<String>,
     ms: Vec<String>,
@@ -561,22 +810,112 @@ pub fn drop_msg_task(mp: &MP) -> Sender<String> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::WxError;
     use assert_json_diff::assert_json_eq;
 
-    #[derive(Deserialize)]
-    struct Conf {
-        corp_id: String,
-        corp_secret: String,
-        agent_id: i64,
-        to_user: String,
-    }
+    use crate::mock::MockServer;
+
     #[tokio::test]
     async fn test_mp() -> Result<()> {
-        let conf: Conf = serde_json::from_str(include_str!("../config.json"))?;
+        let server = MockServer::start().await?;
         let b = include_bytes!("./test_upload.png");
-        let mp = MP::new(&conf.corp_id, &conf.corp_secret, conf.agent_id);
-        mp.send_image_msg(&conf.to_user, b).await?;
-        mp.send_text_msg(&conf.to_user, "hello world").await?;
+        let mp = server.mp();
+        mp.send_image_msg("UserID1", b).await?;
+        let msg_id = mp.send_text_msg("UserID1", "hello world").await?;
+        assert!(msg_id.starts_with("mock_msg_"));
+
+        // token 只需要获取一次
+        assert_eq!(server.requests_to("/cgi-bin/gettoken").len(), 1);
+        assert_eq!(server.requests_to("/cgi-bin/media/upload").len(), 1);
+        let sent = server.requests_to("/cgi-bin/message/send");
+        assert_eq!(sent.len(), 2);
+        let image = sent[0].json()?;
+        assert_eq!(image["msgtype"], "image");
+        assert_eq!(image["agentid"], 1000002);
+        assert!(image["image"]["media_id"]
+            .as_str()
+            .unwrap()
+            .starts_with("mock_media_"));
+        assert_eq!(sent[1].json()?["text"]["content"], "hello world");
+        assert!(sent[1].query["access_token"].starts_with("mock_token_"));
+
+        mp.send_bot_text("机器人消息", &server.bot_url("abc"))
+            .await?;
+        let bot = server.requests_to("/cgi-bin/webhook/send");
+        assert_eq!(bot[0].query["key"], "abc");
+        assert_eq!(bot[0].json()?["text"]["content"], "机器人消息");
+
+        mp.send_file_msg("UserID1", "score.csv", b"name,score")
+            .await?;
+        let upload = server.requests_to("/cgi-bin/media/upload");
+        assert_eq!(upload[1].query["type"], "file");
+        let file = server.requests_to("/cgi-bin/message/send")[2].json()?;
+        assert_eq!(file["msgtype"], "file");
+        assert!(file["file"]["media_id"]
+            .as_str()
+            .unwrap()
+            .starts_with("mock_media_"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token() -> Result<()> {
+        let server = MockServer::start().await?;
+        let mp = server.mp();
+        server.inject_errcode("/cgi-bin/message/send", 42001, "access_token expired");
+        mp.send_text_msg("UserID1", "hello").await?;
+        assert_eq!(server.requests_to("/cgi-bin/gettoken").len(), 2);
+        let sent = server.requests_to("/cgi-bin/message/send");
+        assert_eq!(sent.len(), 2);
+        assert_ne!(sent[0].query["access_token"], sent[1].query["access_token"]);
+
+        // 只重试一次
+        server.inject_errcode("/cgi-bin/message/send", 40014, "invalid access_token");
+        server.inject_errcode("/cgi-bin/message/send", 40014, "invalid access_token");
+        let e = mp.send_text_msg("UserID1", "hello").await.unwrap_err();
+        assert!(matches!(
+            WxError::of(&e),
+            Some(WxError::InvalidToken(40014, _))
+        ));
+
+        server.inject_errcode(
+            "/cgi-bin/message/send",
+            81013,
+            "user & party & tag all invalid",
+        );
+        let e = mp.send_text_msg("nobody", "hello").await.unwrap_err();
+        assert!(matches!(WxError::of(&e), Some(WxError::UserNotFound(..))));
+
+        server.inject_errcode("/cgi-bin/webhook/send", 45009, "api freq out of limit");
+        let e = mp
+            .send_bot_text("hello", &server.bot_url("abc"))
+            .await
+            .unwrap_err();
+        assert!(matches!(WxError::of(&e), Some(WxError::RateLimited(..))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_drop_msg_task() -> Result<()> {
+        let server = MockServer::start().await?;
+        let tx = drop_msg_task(&server.mp());
+        {
+            let _d = DropMsg::new(tx.clone(), vec!["m1".to_string(), "m2".to_string()]);
+        }
+        drop(tx);
+        for _ in 0..50 {
+            if server.requests_to("/cgi-bin/message/recall").len() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let mut recalled = server
+            .requests_to("/cgi-bin/message/recall")
+            .iter()
+            .map(|r| r.json().unwrap()["msgid"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        recalled.sort();
+        assert_eq!(recalled, vec!["m1", "m2"]);
         Ok(())
     }
 
@@ -684,4 +1023,142 @@ mod test {
         t.set_agent_id(666);
         dbg!(serde_json::to_string(&t).unwrap());
     }
+
+    #[tokio::test]
+    async fn test_cards() -> Result<()> {
+        let server = MockServer::start().await?;
+        let mp = server.mp();
+
+        let card = TemplateCard::text_notice("20231201 学习积分情况", "https://example.com/study")
+            .desc("园区排名第 2")
+            .emphasis("30.5", "平均分")
+            .horizontal("当日学霸", "张三")
+            .jump("去学习", "https://example.com/study");
+        mp.send_template_card("UserID1", card).await?;
+
+        let e = mp
+            .send_template_card(
+                "UserID1",
+                TemplateCard {
+                    card_type: "text_notice".to_string(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("card_action"));
+
+        mp.send_interactive_taskcard(
+            "UserID1",
+            InteractiveTaskcard::new("学习提醒", "今天还没学习", "task_1").button(
+                "done",
+                "已学习",
+                "已完成",
+            ),
+        )
+        .await?;
+
+        let thumb = mp.upload_image(include_bytes!("./test_upload.png")).await?;
+        mp.send_mpnews(
+            "UserID1",
+            vec![MpArticle::new("学习周报", &thumb, "<p>本周平均分 30.5</p>")
+                .digest("本周平均分 30.5")],
+        )
+        .await?;
+
+        mp.send_miniprogram_notice(
+            "UserID1",
+            MiniprogramNotice::new("wx123123123123123", "学习提醒")
+                .page("pages/index")
+                .item("今日积分", "30")
+                .emphasis_first_item(),
+        )
+        .await?;
+
+        let sent = server.requests_to("/cgi-bin/message/send");
+        assert_eq!(sent.len(), 4);
+        assert_json_eq!(
+            sent[0].json()?,
+            serde_json::json!({
+                "touser": "UserID1",
+                "msgtype": "template_card",
+                "agentid": 1000002,
+                "template_card": {
+                    "card_type": "text_notice",
+                    "main_title": {"title": "20231201 学习积分情况", "desc": "园区排名第 2"},
+                    "emphasis_content": {"title": "30.5", "desc": "平均分"},
+                    "horizontal_content_list": [{"keyname": "当日学霸", "value": "张三"}],
+                    "jump_list": [{"type": 1, "title": "去学习", "url": "https://example.com/study"}],
+                    "card_action": {"type": 1, "url": "https://example.com/study"}
+                }
+            })
+        );
+        assert_eq!(sent[1].json()?["msgtype"], "interactive_taskcard");
+        assert_eq!(
+            sent[1].json()?["interactive_taskcard"]["btn"][0]["replace_name"],
+            "已完成"
+        );
+        assert_eq!(
+            sent[2].json()?["mpnews"]["articles"][0]["thumb_media_id"],
+            thumb
+        );
+        assert_eq!(
+            sent[3].json()?["miniprogram_notice"]["content_item"][0]["key"],
+            "今日积分"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_builder() -> Result<()> {
+        assert!(SendMsgReq::builder()
+            .build(MsgBody::Text(TextContent::new("hello")))
+            .is_err());
+
+        let server = MockServer::start().await?;
+        let mp = server.mp();
+        let req = SendMsgReq::builder()
+            .to_party("2|3")
+            .safe(true)
+            .duplicate_check(1800)
+            .build(MsgBody::TextCard(
+                TextCardContent::new("学习提醒", "今天还没学习", "https://example.com/study")
+                    .btn_txt("去学习"),
+            ))?;
+        mp.send_msg(req).await?;
+        mp.send_msg(
+            SendMsgReq::builder()
+                .to_tag("1")
+                .build(MsgBody::News(NewsContent {
+                    articles: vec![NewsArticle::new("学习周报", "https://example.com/week")
+                        .pic_url("https://example.com/a.png")],
+                }))?,
+        )
+        .await?;
+
+        let sent = server.requests_to("/cgi-bin/message/send");
+        assert_json_eq!(
+            sent[0].json()?,
+            serde_json::json!({
+                "toparty": "2|3",
+                "msgtype": "textcard",
+                "agentid": 1000002,
+                "safe": 1,
+                "enable_duplicate_check": 1,
+                "duplicate_check_interval": 1800,
+                "textcard": {
+                    "title": "学习提醒",
+                    "description": "今天还没学习",
+                    "url": "https://example.com/study",
+                    "btntxt": "去学习"
+                }
+            })
+        );
+        assert_eq!(sent[1].json()?["totag"], "1");
+        assert_eq!(
+            sent[1].json()?["news"]["articles"][0]["picurl"],
+            "https://example.com/a.png"
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,89 @@
+//! 网页授权登录，参考 https://developer.work.weixin.qq.com/document/path/91022
+//! 只用 snsapi_base 拿到成员 UserID，回调域名需要在应用的“网页授权及JS-SDK”里配置
+
+use crate::MP;
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use serde::Deserialize;
+use tracing::instrument;
+
+const AUTHORIZE_URL: &str = "https://open.weixin.qq.com/connect/oauth2/authorize";
+
+#[derive(Deserialize, Debug)]
+struct UserInfoResponse {
+    #[serde(rename = "userid")]
+    user_id: Option<String>,
+    #[serde(rename = "openid")]
+    open_id: Option<String>,
+}
+
+#[async_trait::async_trait]
+pub trait OAuthApi {
+    /// 构造授权链接，企业微信里打开后会带着 code 和 state 跳转到 redirect_uri
+    fn oauth_url(&self, redirect_uri: &str, state: &str) -> String;
+    /// 用授权回调的 code 换成员 UserID，code 只能使用一次，5 分钟内有效
+    async fn user_id_by_code(&self, code: &str) -> Result<String>;
+}
+
+#[async_trait::async_trait]
+impl OAuthApi for MP {
+    fn oauth_url(&self, redirect_uri: &str, state: &str) -> String {
+        let agent_id = self.agent_id.to_string();
+        let mut url = Url::parse_with_params(
+            AUTHORIZE_URL,
+            &[
+                ("appid", self.corp_id.as_str()),
+                ("redirect_uri", redirect_uri),
+                ("response_type", "code"),
+                ("scope", "snsapi_base"),
+                ("state", state),
+                ("agentid", agent_id.as_str()),
+            ],
+        )
+        .expect("授权地址是合法的 URL");
+        url.set_fragment(Some("wechat_redirect"));
+        url.to_string()
+    }
+
+    #[instrument(skip(self, code))]
+    async fn user_id_by_code(&self, code: &str) -> Result<String> {
+        let r: UserInfoResponse = self
+            .get_json("/cgi-bin/auth/getuserinfo", &[("code", code.to_string())])
+            .await?;
+        match (r.user_id, r.open_id) {
+            (Some(u), _) if !u.is_empty() => Ok(u),
+            (_, Some(_)) => Err(anyhow!("不是企业成员，没有 UserID")),
+            _ => Err(anyhow!("企业微信没有返回 UserID")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockServer;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_oauth() -> Result<()> {
+        let server = MockServer::start().await?;
+        let mp = server.mp();
+        assert_eq!(
+            mp.oauth_url("https://admin.example.com/auth/callback?a=1", "s1"),
+            "https://open.weixin.qq.com/connect/oauth2/authorize?appid=mock_corp_id\
+             &redirect_uri=https%3A%2F%2Fadmin.example.com%2Fauth%2Fcallback%3Fa%3D1\
+             &response_type=code&scope=snsapi_base&state=s1&agentid=1000002#wechat_redirect"
+        );
+
+        assert_eq!(mp.user_id_by_code("code1").await?, "mock_user");
+        let req = server.requests_to("/cgi-bin/auth/getuserinfo");
+        assert_eq!(req[0].query["code"], "code1");
+
+        server.set_response(
+            "/cgi-bin/auth/getuserinfo",
+            json!({"errcode": 0, "errmsg": "ok", "openid": "o1", "external_userid": ""}),
+        );
+        assert!(mp.user_id_by_code("code2").await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+//! 发送队列：按接收方分组发送，群机器人按 webhook 限速，失败按指数退避重试，
+//! 每条消息单独给出发送结果，一个接收方出错不影响其他接收方
+
+use crate::{MsgApi, WxError, MP};
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tracing::{debug, instrument, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// 应用消息，多个 UserID 用 `|` 分隔
+    User(String),
+    /// 群机器人 webhook 地址
+    Bot(String),
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::User(u) => write!(f, "user:{}", u),
+            Target::Bot(api) => {
+                // webhook 的 key 不打到日志里
+                let api = api.split("key=").next().unwrap_or(api);
+                write!(f, "bot:{}", api)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Payload {
+    Text(String),
+    Markdown(String),
+    Image(Vec<u8>),
+}
+
+impl Payload {
+    fn kind(&self) -> &'static str {
+        match self {
+            Payload::Text(_) => "text",
+            Payload::Markdown(_) => "markdown",
+            Payload::Image(_) => "image",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutMsg {
+    pub target: Target,
+    pub payload: Payload,
+}
+
+impl OutMsg {
+    pub fn new(target: Target, payload: Payload) -> Self {
+        Self { target, payload }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeliveryStatus {
+    Sent,
+    Failed(String),
+}
+
+/// 单条消息的发送结果
+#[derive(Debug, Clone)]
+pub struct DeliveryReport {
+    pub target: Target,
+    pub kind: &'static str,
+    pub attempts: u32,
+    pub status: DeliveryStatus,
+}
+
+impl DeliveryReport {
+    pub fn is_sent(&self) -> bool {
+        self.status == DeliveryStatus::Sent
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboxConfig {
+    pub bot_rate: usize, // 每个群机器人在 rate_window 内最多发送的条数，企业微信限制 20 条/分钟
+    pub rate_window: Duration, // 限速窗口
+    pub max_attempts: u32, // 每条消息最多尝试次数
+    pub base_delay: Duration, // 第一次重试前等待时间，之后每次翻倍
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            bot_rate: 20,
+            rate_window: Duration::from_secs(60),
+            max_attempts: 4,
+            base_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+/// 可以 clone 后在多个线程、多个 tokio runtime 间共享，限速状态是共用的
+#[derive(Clone)]
+pub struct Outbox {
+    mp: MP,
+    conf: OutboxConfig,
+    sent_at: Arc<Mutex<HashMap<String, VecDeque<Instant>>>>,
+}
+
+impl Outbox {
+    pub fn new(mp: MP) -> Self {
+        Self::with_config(mp, OutboxConfig::default())
+    }
+
+    pub fn with_config(mp: MP, conf: OutboxConfig) -> Self {
+        Self {
+            mp,
+            conf,
+            sent_at: Default::default(),
+        }
+    }
+
+    /// 同一个接收方的消息按顺序发送，不同接收方并发发送，返回结果的顺序与 msgs 一致
+    #[instrument(skip_all, fields(count = msgs.len()))]
+    pub async fn send_all(&self, msgs: Vec<OutMsg>) -> Vec<DeliveryReport> {
+        let mut groups: Vec<(Target, Vec<(usize, Payload)>)> = vec![];
+        let total = msgs.len();
+        for (i, m) in msgs.into_iter().enumerate() {
+            match groups.iter_mut().find(|(t, _)| *t == m.target) {
+                Some((_, g)) => g.push((i, m.payload)),
+                None => groups.push((m.target, vec![(i, m.payload)])),
+            }
+        }
+
+        let mut set = JoinSet::new();
+        for (target, payloads) in groups {
+            let outbox = self.clone();
+            set.spawn(async move {
+                let mut r = vec![];
+                for (i, payload) in payloads {
+                    r.push((i, outbox.deliver(&target, &payload).await));
+                }
+                r
+            });
+        }
+
+        let mut reports: Vec<Option<DeliveryReport>> = vec![None; total];
+        while let Some(r) = set.join_next().await {
+            match r {
+                Ok(r) => {
+                    for (i, report) in r {
+                        reports[i] = Some(report);
+                    }
+                }
+                Err(e) => warn!("发送任务异常退出: {}", e),
+            }
+        }
+        reports.into_iter().flatten().collect()
+    }
+
+    /// 发送一条消息，限流和网络错误会重试，其他错误直接失败
+    pub async fn deliver(&self, target: &Target, payload: &Payload) -> DeliveryReport {
+        let mut attempts = 0;
+        let status = loop {
+            attempts += 1;
+            if let Target::Bot(api) = target {
+                self.acquire(api).await;
+            }
+            let e = match self.send(target, payload).await {
+                Ok(_) => break DeliveryStatus::Sent,
+                Err(e) => e,
+            };
+            let retryable = matches!(WxError::of(&e), None | Some(WxError::RateLimited(..)));
+            if !retryable || attempts >= self.conf.max_attempts {
+                warn!(%target, attempts, "发送 {} 消息失败: {}", payload.kind(), e);
+                break DeliveryStatus::Failed(e.to_string());
+            }
+            let delay = self.conf.base_delay * 2u32.pow(attempts - 1);
+            debug!(%target, attempts, "发送失败，{:?} 后重试: {}", delay, e);
+            tokio::time::sleep(delay).await;
+        };
+        DeliveryReport {
+            target: target.clone(),
+            kind: payload.kind(),
+            attempts,
+            status,
+        }
+    }
+
+    async fn send(&self, target: &Target, payload: &Payload) -> Result<()> {
+        match (target, payload) {
+            (Target::User(u), Payload::Text(s)) => self.mp.send_text_msg(u, s).await.map(|_| ()),
+            (Target::User(u), Payload::Markdown(s)) => {
+                self.mp.send_markdown_msg(u, s).await.map(|_| ())
+            }
+            (Target::User(u), Payload::Image(img)) => {
+                self.mp.send_image_msg(u, img).await.map(|_| ())
+            }
+            (Target::Bot(api), Payload::Text(s)) => self.mp.send_bot_text(s, api).await,
+            (Target::Bot(api), Payload::Markdown(s)) => self.mp.send_bot_msg(s, api).await,
+            (Target::Bot(api), Payload::Image(img)) => self.mp.send_bot_image(img, api).await,
+        }
+    }
+
+    /// 滑动窗口限速，窗口内已满时等到最早的一条过期
+    async fn acquire(&self, key: &str) {
+        loop {
+            let wait = {
+                let mut m = self.sent_at.lock().unwrap();
+                let q = m.entry(key.to_string()).or_default();
+                let now = Instant::now();
+                while q
+                    .front()
+                    .is_some_and(|t| now.duration_since(*t) >= self.conf.rate_window)
+                {
+                    q.pop_front();
+                }
+                if q.len() < self.conf.bot_rate {
+                    q.push_back(now);
+                    return;
+                }
+                self.conf.rate_window - now.duration_since(q[0])
+            };
+            debug!("群机器人发送太快，等待 {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockServer;
+
+    fn conf() -> OutboxConfig {
+        OutboxConfig {
+            bot_rate: 2,
+            rate_window: Duration::from_millis(300),
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bad_bot_not_block_others() -> Result<()> {
+        let server = MockServer::start().await?;
+        let outbox = Outbox::with_config(server.mp(), conf());
+        let bad = server.bot_url("bad");
+        let good = server.bot_url("good");
+        // 93000 webhook 地址无效，不重试
+        server.inject_errcode("/cgi-bin/webhook/send", 93000, "invalid webhook url");
+
+        let reports = outbox
+            .send_all(vec![
+                OutMsg::new(Target::Bot(bad.clone()), Payload::Text("a".to_string())),
+                OutMsg::new(
+                    Target::User("UserID1".to_string()),
+                    Payload::Text("b".to_string()),
+                ),
+            ])
+            .await;
+        assert_eq!(reports.len(), 2);
+        assert!(!reports[0].is_sent());
+        assert_eq!(reports[0].attempts, 1);
+        assert!(reports[1].is_sent());
+
+        // 45009 限流，退避后重试成功
+        server.inject_errcode("/cgi-bin/webhook/send", 45009, "api freq out of limit");
+        let r = outbox
+            .deliver(&Target::Bot(good), &Payload::Markdown("c".to_string()))
+            .await;
+        assert!(r.is_sent());
+        assert_eq!(r.attempts, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bot_rate_limit() -> Result<()> {
+        let server = MockServer::start().await?;
+        let outbox = Outbox::with_config(server.mp(), conf());
+        let bot = Target::Bot(server.bot_url("a"));
+
+        let start = Instant::now();
+        let reports = outbox
+            .send_all(
+                (0..3)
+                    .map(|i| OutMsg::new(bot.clone(), Payload::Text(i.to_string())))
+                    .collect(),
+            )
+            .await;
+        assert!(reports.iter().all(|r| r.is_sent()));
+        assert!(start.elapsed() >= Duration::from_millis(300));
+
+        let sent = server.requests_to("/cgi-bin/webhook/send");
+        let texts = sent
+            .iter()
+            .map(|r| {
+                Ok(r.json()?["text"]["content"]
+                    .as_str()
+                    .unwrap_or("")
+                    .to_string())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(texts, vec!["0", "1", "2"]);
+        Ok(())
+    }
+}